@@ -0,0 +1,154 @@
+//! Measure how expensive the generated macros are for large signatures.
+//!
+//! For each size, this writes a scratch crate to `target/optargs-bench` with an optfn and an
+//! OptStruct taking that many arguments, half required and half optional, plus a bunch of call
+//! sites. It then reports the size of the expanded source and how long `cargo check` takes.
+//!
+//! Run it with `cargo bench --bench expansion`. Expanding the source needs `-Zunpretty`, so the
+//! expanded size is only measured when a nightly toolchain is installed through rustup, and shown
+//! as `-` otherwise.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const SIZES: &[usize] = &[5, 10, 20, 40];
+const CALL_SITES: usize = 100;
+
+fn main() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let dir = root.join("target").join("optargs-bench");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+
+    write_manifest(&root, &dir);
+
+    // Build the dependencies once so they don't end up in the first measurement
+    write_source(&dir, 1, 1);
+    run(&cargo, &dir, &["check", "--quiet"]);
+
+    println!(
+        "{:>6} {:>6} {:>14} {:>10}",
+        "args", "calls", "expanded (kB)", "check"
+    );
+    for &size in SIZES {
+        write_source(&dir, size, CALL_SITES);
+
+        let expanded = match expanded_size(&dir) {
+            Some(bytes) => format!("{:.1}", bytes as f64 / 1024.0),
+            None => "-".to_string(),
+        };
+        let check = check_time(&cargo, &dir);
+
+        println!(
+            "{:>6} {:>6} {:>14} {:>9.2}s",
+            size,
+            CALL_SITES,
+            expanded,
+            check.as_secs_f64()
+        );
+    }
+}
+
+fn write_manifest(root: &Path, dir: &Path) {
+    fs::create_dir_all(dir.join("src")).unwrap();
+    let manifest = format!(
+        r#"[package]
+name = "optargs-bench"
+version = "0.0.0"
+edition = "2018"
+publish = false
+
+[dependencies]
+optargs = {{ path = {:?} }}
+
+[workspace]
+"#,
+        root
+    );
+    fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+}
+
+// A function and a struct with `size` arguments, called `calls` times each
+fn write_source(dir: &Path, size: usize, calls: usize) {
+    let required = size - size / 2;
+
+    let mut src = String::from("#![allow(unused)]\n\n");
+    let params: Vec<String> = (0..size)
+        .map(|i| match i < required {
+            true => format!("a{}: u32", i),
+            false => format!("a{}: Option<u32>", i),
+        })
+        .collect();
+
+    writeln!(
+        src,
+        "#[optargs::optfn]\npub fn wide({}) -> u32 {{ a0 }}\n",
+        params.join(", ")
+    )
+    .unwrap();
+    writeln!(
+        src,
+        "#[derive(optargs::OptStruct)]\npub struct Wide {{ {} }}\n",
+        params
+            .iter()
+            .map(|p| format!("pub {},", p))
+            .collect::<String>()
+    )
+    .unwrap();
+
+    writeln!(src, "fn main() {{").unwrap();
+    for call in 0..calls {
+        // every required argument, and a different slice of the optional ones each time
+        let args: Vec<String> = (0..size)
+            .filter(|&i| i < required || (i + call) % 3 == 0)
+            .map(|i| format!("a{}: {}", i, call))
+            .collect();
+        let args = args.join(", ");
+        writeln!(src, "    let _ = wide!({});", args).unwrap();
+        writeln!(src, "    let _ = Wide!({});", args).unwrap();
+    }
+    writeln!(src, "}}").unwrap();
+
+    fs::write(dir.join("src").join("main.rs"), src).unwrap();
+}
+
+// `None` without a nightly toolchain. This goes through the rustup proxy rather than `$CARGO`,
+// which is the stable cargo itself
+fn expanded_size(dir: &Path) -> Option<usize> {
+    let out = Command::new("cargo")
+        .args(["+nightly", "rustc", "--quiet", "--", "-Zunpretty=expanded"])
+        // a different toolchain rebuilds the dependencies, so keep them away from the check builds
+        .env("CARGO_TARGET_DIR", dir.join("target-expand"))
+        .current_dir(dir)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    match out.status.success() {
+        true => Some(out.stdout.len()),
+        false => None,
+    }
+}
+
+fn check_time(cargo: &str, dir: &Path) -> Duration {
+    // only rebuild the bench crate itself, not optargs
+    run(
+        cargo,
+        dir,
+        &["clean", "--quiet", "--package", "optargs-bench"],
+    );
+
+    let start = Instant::now();
+    run(cargo, dir, &["check", "--quiet"]);
+    start.elapsed()
+}
+
+fn run(cargo: &str, dir: &Path, args: &[&str]) {
+    let status = Command::new(cargo)
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "`cargo {}` failed", args.join(" "));
+}
@@ -0,0 +1,57 @@
+//! Every optfn has a `plot::ARGS` and every OptStruct a `Scatter::ARGS`, describing their arguments
+//! at compile time. Handy for help text, GUIs and tests.
+
+use optargs::ArgInfo;
+
+/// Plot some points
+#[optargs::optfn]
+fn plot(
+    /// The points to plot
+    x: Vec<i32>,
+    /// Shown above the plot
+    title: Option<&str>,
+    #[optarg(default = 1.0)] scale: f32,
+    #[optarg(rest)] labels: Vec<&str>,
+) {
+    let _ = (x, title, scale, labels);
+}
+
+#[derive(optargs::OptStruct)]
+pub struct Scatter {
+    /// The x coordinates
+    pub x: Vec<i32>,
+    #[optarg(name = "points")]
+    pub y: Option<Vec<i32>>,
+    #[optarg(default)]
+    pub filled: bool,
+}
+
+fn help(name: &str, args: &[ArgInfo]) -> String {
+    let mut out = format!("{}:", name);
+    for arg in args {
+        out += &format!("\n  {}: {}", arg.name, arg.type_name);
+        match (arg.required, arg.default) {
+            (true, _) => out += " [required]",
+            (false, Some(default)) => out += &format!(" [default: {}]", default),
+            (false, None) => {}
+        }
+        if !arg.doc.is_empty() {
+            out += &format!("  {}", arg.doc);
+        }
+    }
+    out
+}
+
+fn main() {
+    println!("{}", help("plot", plot::ARGS));
+    println!("{}", help("Scatter", Scatter::ARGS));
+
+    let required: Vec<&str> = Scatter::ARGS
+        .iter()
+        .filter(|arg| arg.required)
+        .map(|arg| arg.name)
+        .collect();
+    assert_eq!(required, ["x"]);
+    assert_eq!(Scatter::ARGS[1].name, "points");
+    assert_eq!(Scatter::ARGS[2].default, Some("Default::default()"));
+}
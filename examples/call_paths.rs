@@ -0,0 +1,51 @@
+//! `optargs::call!` and `optargs::make!` find their target through normal path resolution, so
+//! unlike `plot!` and `Scatter!` they work before the declaration and across modules.
+
+use charts::Scatter;
+
+fn main() {
+    // `plot` is declared further down, and reached through its module
+    let plot = optargs::call!(charts::plot, x: vec![1, 2, 3], title: "hello");
+    assert_eq!(plot, r#"[1, 2, 3] Some("hello") 1"#);
+
+    // shorthands work too, and defaults are filled in
+    let x = vec![4, 5];
+    let scale = 2.0;
+    println!("{}", optargs::call!(charts::plot, x, scale));
+
+    // imported with `use`
+    let scatter = optargs::make!(Scatter, points: vec![(1, 2)], label: "a");
+    println!("{:?} {:?}", scatter.points, scatter.label);
+
+    // generic structs are inferred from the values
+    let tagged = optargs::make!(charts::Tagged, value: 'x');
+    assert_eq!((tagged.value, tagged.tag), ('x', "untagged"));
+
+    // positional arguments go to the rest argument, after the keys
+    assert_eq!(optargs::call!(charts::total, scale: 2, 1, 2, 3), 12);
+}
+
+mod charts {
+    #[optargs::optfn]
+    pub fn plot(x: Vec<i32>, title: Option<&str>, #[optarg(default = 1.0)] scale: f32) -> String {
+        format!("{:?} {:?} {}", x, title, scale)
+    }
+
+    #[optargs::optfn]
+    pub fn total(scale: i32, #[optarg(rest)] values: Vec<i32>) -> i32 {
+        scale * values.iter().sum::<i32>()
+    }
+
+    #[derive(optargs::OptStruct)]
+    pub struct Scatter {
+        pub points: Vec<(i32, i32)>,
+        pub label: Option<&'static str>,
+    }
+
+    #[derive(optargs::OptStruct)]
+    pub struct Tagged<T> {
+        pub value: T,
+        #[optarg(default = "untagged")]
+        pub tag: &'static str,
+    }
+}
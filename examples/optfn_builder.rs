@@ -0,0 +1,63 @@
+//! Besides `plot!(...)`, every optfn can be called as `plot::builder().x(v).title("t").call()`.
+//! The builder is a `PlotBuilder`, which can be filled in across branches, passed to helpers and
+//! stored before it runs. Like `plot!`, leaving out a required argument is a compile error, since
+//! `call` only exists once all of them were passed.
+
+use std::fmt::Display;
+
+#[optargs::optfn]
+fn plot(
+    x: Vec<i32>,
+    y: Option<Vec<i32>>,
+    #[optarg(default = "untitled")] title: &str,
+    #[optarg(repeated)] labels: Vec<String>,
+) -> String {
+    format!("{} {:?} {:?} {:?}", title, x, y, labels)
+}
+
+#[optargs::optfn]
+fn describe<T: Display>(
+    value: T,
+    unit: Option<impl Display>,
+    #[optarg(rest)] notes: Vec<&str>,
+) -> String {
+    match unit {
+        Some(unit) => format!("{} {} {:?}", value, unit, notes),
+        None => format!("{} {:?}", value, notes),
+    }
+}
+
+// helpers can take a builder in any state, and only set optional arguments
+fn labelled<'a, M>(builder: PlotBuilder<'a, M>, labels: &[&str]) -> PlotBuilder<'a, M> {
+    labels
+        .iter()
+        .fold(builder, |builder, label| builder.labels(label.to_string()))
+}
+
+fn main() {
+    let squares = true;
+
+    let mut builder = plot::builder().title("chart");
+    builder = match squares {
+        true => builder.y(vec![1, 4, 9]),
+        false => builder.y(vec![1, 2, 3]),
+    };
+    let builder = labelled(builder, &["a", "b"]);
+
+    // the required `x` is passed last, right before the call
+    let stored = builder.x(vec![1, 2, 3]);
+    let out = stored.call();
+    assert_eq!(out, r#"chart [1, 2, 3] Some([1, 4, 9]) ["a", "b"]"#);
+    println!("{}", out);
+
+    assert_eq!(plot::builder().x(vec![1]).call(), r#"untitled [1] None []"#);
+
+    // generics are inferred from the arguments, and the rest argument has a setter of its own
+    let out = describe::builder()
+        .value(3.5)
+        .unit("m")
+        .notes(vec!["measured"])
+        .call();
+    assert_eq!(out, r#"3.5 m ["measured"]"#);
+    println!("{}", out);
+}
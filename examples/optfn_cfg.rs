@@ -0,0 +1,64 @@
+//! Parameters and fields behind a `#[cfg]` only become keys when they're enabled.
+//!
+//! Run with `--features serde` to turn on `format`. Passing a disabled key is a compile error
+//! that names the `#[cfg]`, ie "`format` is disabled by #[cfg(feature = "serde")]".
+
+#[optargs::optfn]
+fn export(
+    rows: Vec<i32>,
+    #[cfg(feature = "serde")] format: &str,
+    #[cfg(not(feature = "serde"))] separator: Option<char>,
+    #[optarg(default = false)] header: bool,
+) -> String {
+    #[cfg(feature = "serde")]
+    let body = format!("{} {:?}", format, rows);
+    #[cfg(not(feature = "serde"))]
+    let body = rows
+        .iter()
+        .map(|row| row.to_string())
+        .collect::<Vec<_>>()
+        .join(&separator.unwrap_or(',').to_string());
+
+    match header {
+        true => format!("rows\n{}", body),
+        false => body,
+    }
+}
+
+#[derive(optargs::OptStruct)]
+struct Export {
+    rows: Vec<i32>,
+    #[cfg(feature = "serde")]
+    format: &'static str,
+    #[cfg(not(feature = "serde"))]
+    separator: Option<char>,
+}
+
+fn main() {
+    // `format` is only required when it exists
+    #[cfg(feature = "serde")]
+    let out = export!(rows: vec![1, 2], format: "json", header: true);
+    #[cfg(not(feature = "serde"))]
+    let out = export!(rows: vec![1, 2], separator: ';', header: true);
+    println!("{}", out);
+
+    #[cfg(feature = "serde")]
+    {
+        let made = Export!(rows: vec![3], format: "json");
+        println!("{:?} {}", made.rows, made.format);
+    }
+    #[cfg(not(feature = "serde"))]
+    {
+        let made = Export!(rows: vec![3]);
+        println!("{:?} {:?}", made.rows, made.separator);
+    }
+
+    // `call!` goes through the same set of keys
+    #[cfg(not(feature = "serde"))]
+    assert_eq!(optargs::call!(export, rows: vec![1, 2, 3]), "1,2,3");
+    #[cfg(feature = "serde")]
+    assert_eq!(
+        optargs::call!(export, rows: vec![1], format: "csv"),
+        "csv [1]"
+    );
+}
@@ -1,7 +1,10 @@
+/// Send GME to the moon
 #[optargs::optfn]
 fn go_gme(
+    /// The price per share
     price: f32,
     to_the_moon: Option<bool>,
+    /// How many rockets to launch
     rocket_ships: Option<usize>,
     doges: Option<usize>,
     tendies: Option<bool>,
@@ -0,0 +1,33 @@
+//! Keys marked `#[optarg(deprecated = "...")]` still work, but passing them is a `deprecated`
+//! warning that points at the key:
+//!
+//!     warning: use of deprecated method `plot::Validator::<M0>::colour`: use `color` instead
+//!       |
+//!       |     plot!(x: vec![1, 2, 3], colour: "red");
+//!       |                             ^^^^^^
+
+#![allow(deprecated)]
+
+#[optargs::optfn]
+fn plot(
+    x: Vec<i32>,
+    color: Option<&str>,
+    #[optarg(deprecated = "use `color` instead")] colour: Option<&str>,
+) -> String {
+    format!("{:?} in {}", x, color.or(colour).unwrap_or("black"))
+}
+
+#[derive(optargs::OptStruct)]
+struct Scatter {
+    x: Vec<i32>,
+    #[optarg(deprecated = "points are always drawn now")]
+    show_points: Option<bool>,
+}
+
+fn main() {
+    println!("{}", plot!(x: vec![1, 2, 3], colour: "red"));
+    println!("{}", optargs::call!(plot, x: vec![1], colour: "blue"));
+
+    let scatter = Scatter!(x: vec![1], show_points: true);
+    println!("{:?} {:?}", scatter.x, scatter.show_points);
+}
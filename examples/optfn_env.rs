@@ -0,0 +1,38 @@
+//! Optional arguments can fall back to an environment variable when they're left out.
+//!
+//! PLOT_TITLE="from the env" cargo run --example optfn_env
+
+#[optargs::optfn]
+fn plot(
+    x: Vec<i32>,
+    #[optarg(env = "PLOT_TITLE")] title: Option<String>,
+    #[optarg(env = "PLOT_WIDTH", default = 80)] width: usize,
+) {
+    println!("{:?} {:?} {}", x, title, width);
+}
+
+#[derive(optargs::OptStruct)]
+struct Service {
+    name: &'static str,
+
+    #[optarg(env = "SERVICE_WORKERS", default = 4)]
+    workers: usize,
+}
+
+fn main() {
+    // uses the environment if it's set
+    plot!(x: vec![1, 2, 3]);
+
+    // explicit keys always win
+    plot!(x: vec![1, 2, 3], title: "explicit".to_string(), width: 40);
+
+    // a value that doesn't parse is reported to the hook and ignored, so the width falls back to 80
+    optargs::env::set_invalid_hook(|err| eprintln!("warning: ignoring {}", err));
+    std::env::set_var("PLOT_WIDTH", "wide");
+    plot!(x: vec![1, 2, 3]);
+
+    std::env::set_var("SERVICE_WORKERS", "16");
+    let service = Service! { name: "api" };
+    assert_eq!(service.workers, 16);
+    println!("{} runs {} workers", service.name, service.workers);
+}
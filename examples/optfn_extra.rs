@@ -0,0 +1,49 @@
+//! A parameter marked `#[optarg(extra)]` collects every key that isn't a parameter, like
+//! `**kwargs` in python. It can be a `Vec<(&'static str, T)>`, which keeps the keys in call order,
+//! or a `HashMap<&'static str, T>`.
+
+use std::collections::HashMap;
+
+#[optargs::optfn]
+fn element(
+    tag: &str,
+    class: Option<&str>,
+    #[optarg(extra)] attrs: Vec<(&'static str, String)>,
+) -> String {
+    let mut out = format!("<{}", tag);
+    if let Some(class) = class {
+        out += &format!(" class=\"{}\"", class);
+    }
+    for (key, value) in attrs {
+        out += &format!(" {}=\"{}\"", key, value);
+    }
+    out + ">"
+}
+
+#[optargs::optfn]
+fn config(name: &str, #[optarg(extra)] vars: HashMap<&'static str, i32>) -> String {
+    let mut vars: Vec<_> = vars.into_iter().collect();
+    vars.sort();
+    format!("{}: {:?}", name, vars)
+}
+
+fn main() {
+    let href = "/home".to_string();
+    let link = element!(tag: "a", href, class: "nav", title: "Home".to_string());
+    assert_eq!(link, r#"<a class="nav" href="/home" title="Home">"#);
+    println!("{}", link);
+
+    // `key?:` only adds the key when there's a value
+    let id: Option<String> = None;
+    println!("{}", element!(tag: "div", id?: id));
+
+    println!("{}", config!(name: "prod", workers: 4, port: 8080));
+    println!("{}", config!(name: "dev"));
+
+    // keys passed next to a spread are collected too
+    let args = ElementArgs {
+        class: Some("icon"),
+        ..Default::default()
+    };
+    println!("{}", element!(tag: "img", src: "a.png".to_string(), ..args));
+}
@@ -0,0 +1,45 @@
+//! Every optfn also gets an `Args` struct holding all of its arguments as options.
+//! It can be spread into a call with `..args`, which makes forwarding arguments easy. Keys passed
+//! explicitly win over the spread, and required arguments may come from either.
+
+#[optargs::optfn]
+pub fn plot(
+    x: Vec<i32>,
+    y: Option<Vec<i32>>,
+    title: Option<&str>,
+    #[optarg(default = 1.0)] scale: f32,
+) -> String {
+    format!("{:?} {:?} {:?} {}", x, y, title, scale)
+}
+
+// wrappers can take the optional arguments without listing each one
+fn plot_squares(x: Vec<i32>, args: PlotArgs) -> String {
+    let y = x.iter().map(|x| x * x).collect();
+    plot!(x: x, y: y, ..args)
+}
+
+fn main() {
+    let args = PlotArgs {
+        title: Some("squares"),
+        ..Default::default()
+    };
+    assert_eq!(
+        plot_squares(vec![1, 2, 3], args),
+        r#"[1, 2, 3] Some([1, 4, 9]) Some("squares") 1"#
+    );
+
+    // keys passed explicitly win over the spread
+    let args = PlotArgs {
+        title: Some("from args"),
+        scale: Some(2.0),
+        ..Default::default()
+    };
+    println!("{}", plot!(x: vec![1], title: "spread", ..args));
+
+    // including the required ones
+    let args = PlotArgs {
+        x: Some(vec![4, 5]),
+        ..Default::default()
+    };
+    assert_eq!(plot!(..args), "[4, 5] None None 1");
+}
@@ -0,0 +1,85 @@
+//! Groups of optional arguments, checked at compile time.
+//!
+//! Arguments in an `exclusive` group can't be passed together, and exactly one argument of a
+//! `required` group must be. Breaking either is a compile error with the group's keys, ie
+//! "exactly one of `path`, `url` or `bytes` must be passed (group `source`)". Spreading `..args`
+//! checks the groups at runtime instead, and panics with the same message. Partials and builders
+//! return it as an error.
+
+#[optargs::optfn]
+fn load(
+    name: &str,
+    #[optarg(group = "source", required)] path: Option<&str>,
+    #[optarg(group = "source", required)] url: Option<&str>,
+    #[optarg(group = "source", required)] bytes: Option<Vec<u8>>,
+    #[optarg(group = "format", exclusive)] json: Option<bool>,
+    #[optarg(group = "format", exclusive)] csv: Option<bool>,
+) -> String {
+    let source = match (path, url, bytes) {
+        (Some(path), _, _) => format!("file {}", path),
+        (_, Some(url), _) => format!("url {}", url),
+        (_, _, Some(bytes)) => format!("{} bytes", bytes.len()),
+        _ => unreachable!(),
+    };
+    let format = match (json, csv) {
+        (Some(true), _) => "json",
+        (_, Some(true)) => "csv",
+        _ => "raw",
+    };
+    format!("{}: {} as {}", name, source, format)
+}
+
+#[derive(Debug, optargs::OptStruct)]
+#[optstruct(partial)]
+struct Palette {
+    name: &'static str,
+    #[optarg(group = "colors", exclusive)]
+    rgb: Option<Vec<(u8, u8, u8)>>,
+    #[optarg(group = "colors", exclusive)]
+    hex: Option<Vec<&'static str>>,
+}
+
+fn main() {
+    println!("{}", load!(name: "a", path: "data.json", json: true));
+    println!("{}", load!(url: "example.com", name: "b"));
+    println!(
+        "{}",
+        optargs::call!(load, name: "c", bytes: vec![1, 2], csv: true)
+    );
+
+    // none of these compile, with these errors:
+    // load!(name: "d");
+    //     exactly one of `path`, `url` or `bytes` must be passed (group `source`)
+    // load!(name: "d", path: "p", url: "u");
+    //     exactly one of `path`, `url` or `bytes` must be passed (group `source`)
+    // load!(name: "d", path: "p", json: true, csv: true);
+    //     only one of `json` or `csv` can be passed (group `format`)
+    // load!(name: "d", path?: None::<&str>);
+    //     `path` is in the required group `source`, so it can't be passed as `path?:`
+
+    let args = LoadArgs {
+        path: Some("data.csv"),
+        ..Default::default()
+    };
+    println!("{}", load!(name: "e", csv: true, ..args));
+
+    // this one compiles, but panics once it runs, since the spread sets `path` too:
+    // load!(name: "f", url: "example.com", ..LoadArgs { path: Some("p"), ..Default::default() });
+
+    let palette = Palette!(name: "warm", hex: vec!["#f80", "#f00"]);
+    println!("{} {:?} {:?}", palette.name, palette.rgb, palette.hex);
+
+    let palette = optargs::make!(Palette, name: "none");
+    assert!(palette.rgb.is_none() && palette.hex.is_none());
+
+    // fields that are only known at runtime are checked by `finalize`, which returns the message
+    let both = PartialPalette {
+        name: Some("both"),
+        rgb: Some(vec![(255, 0, 0)]),
+        hex: Some(vec!["#f00"]),
+    };
+    match both.finalize() {
+        Err(optargs::BuildError::Rule(broken)) => println!("{}", broken),
+        other => panic!("expected a broken group, got {:?}", other),
+    }
+}
@@ -0,0 +1,59 @@
+//! Parameters can be `impl Trait`, like closures and iterators.
+//!
+//! An `Option<impl Trait>` that's left out falls back to `Box<dyn Trait>`, since there's no value
+//! to infer its type from. Only traits the box implements are accepted there, like `Fn` or
+//! `Display`.
+
+use std::fmt::Display;
+
+#[optargs::optfn]
+fn apply(
+    values: impl IntoIterator<Item = i32>,
+    f: impl Fn(i32) -> i32,
+    filter: Option<impl FnMut(&i32) -> bool>,
+    label: Option<impl Display>,
+) -> String {
+    let mut filter = filter;
+    let out: Vec<i32> = values
+        .into_iter()
+        .filter(|v| match filter.as_mut() {
+            Some(filter) => filter(v),
+            None => true,
+        })
+        .map(f)
+        .collect();
+
+    match label {
+        Some(label) => format!("{}: {:?}", label, out),
+        None => format!("{:?}", out),
+    }
+}
+
+fn main() {
+    // closures and iterators
+    assert_eq!(apply!(values: 1..4, f: |x| x * 2), "[2, 4, 6]");
+
+    let offset = 10;
+    let f = move |x| x + offset;
+    assert_eq!(
+        apply!(values: vec![1, 2, 3], f, filter: |x: &i32| x % 2 == 1),
+        "[11, 13]"
+    );
+
+    // any order, any Display
+    let squares = apply!(label: 'x', f: |x| x * x, values: [1, 2, 3].iter().copied());
+    assert_eq!(squares, "x: [1, 4, 9]");
+    println!("{}", squares);
+
+    // `call!` and `apply::builder()` fall back the same way
+    let negated = optargs::call!(apply, values: 1..3, f: |x| -x, label: "negated");
+    assert_eq!(negated, "negated: [-1, -2]");
+    println!("{}", negated);
+
+    let odd = apply::builder()
+        .values(1..6)
+        .filter(|x: &i32| x % 2 == 1)
+        .f(|x| x)
+        .call();
+    assert_eq!(odd, "[1, 3, 5]");
+}
@@ -0,0 +1,46 @@
+//! `key?: value` passes an `Option` straight through to an optional argument. `None` acts as if
+//! the key was left out, so defaults and `env` still apply.
+
+#[optargs::optfn]
+fn plot(
+    x: Vec<i32>,
+    title: Option<&str>,
+    #[optarg(default = 2)] width: u32,
+    #[optarg(rest)] labels: Vec<&str>,
+) -> String {
+    format!("{:?} {:?} {} {:?}", x, title, width, labels)
+}
+
+#[derive(optargs::OptStruct)]
+struct Scatter {
+    x: Vec<i32>,
+    title: Option<&'static str>,
+    #[optarg(default = 1.0)]
+    scale: f32,
+    #[optarg(repeated)]
+    tags: Vec<&'static str>,
+}
+
+fn main() {
+    let maybe_title = Some("hello");
+    let no_width: Option<u32> = None;
+
+    let out = plot!(x: vec![1, 2], title?: maybe_title, width?: no_width, "a", "b");
+    assert_eq!(out, r#"[1, 2] Some("hello") 2 ["a", "b"]"#);
+    println!("{}", out);
+
+    // shorthand keys, `key: value` and `key?: value` mix freely
+    let x = vec![3];
+    let title = None;
+    println!("{}", plot!(x, title?: title, width: 4));
+
+    let scatter = Scatter!(x: vec![1], title?: Some("s"), scale?: None, tags?: Some("a"));
+    assert_eq!(scatter.title, Some("s"));
+    assert_eq!(scatter.scale, 1.0);
+    assert_eq!(scatter.tags, vec!["a"]);
+    println!("{:?} {:?} {}", scatter.x, scatter.title, scatter.scale);
+
+    // required keys can't be passed as an `Option`:
+    // plot!(x?: Some(vec![1]));
+    // error: `x` is required, so it can't be passed as `x?:`
+}
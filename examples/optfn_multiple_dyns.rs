@@ -1,5 +1,7 @@
 #[optargs::optfn]
-fn blah2(a: Option<&dyn Iterator<Item = i32>>) {}
+fn blah2(a: Option<&dyn Iterator<Item = i32>>) {
+    let _ = a;
+}
 
 fn main() {
     blah2! {
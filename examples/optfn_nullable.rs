@@ -0,0 +1,67 @@
+//! `#[optarg(nullable)]` tells a left out key apart from one that's explicitly null, which is
+//! what PATCH-style updates need. The argument is an `Option<Option<T>>`:
+//!
+//! - left out, it's `None`
+//! - `key: null` or `key: None` makes it `Some(None)`
+//! - `key: value` makes it `Some(Some(value))`
+
+#[derive(Debug, Default)]
+struct User {
+    name: String,
+    email: Option<String>,
+    age: Option<u8>,
+}
+
+#[optargs::optfn]
+fn update(
+    user: &mut User,
+    name: Option<String>,
+    #[optarg(nullable)] email: Option<Option<String>>,
+    #[optarg(nullable)] age: Option<Option<u8>>,
+) {
+    if let Some(name) = name {
+        user.name = name;
+    }
+    if let Some(email) = email {
+        user.email = email;
+    }
+    if let Some(age) = age {
+        user.age = age;
+    }
+}
+
+#[derive(optargs::OptStruct)]
+struct Patch {
+    #[optarg(nullable)]
+    email: Option<Option<&'static str>>,
+}
+
+fn main() {
+    let mut user = User {
+        name: "ada".to_string(),
+        email: Some("ada@example.com".to_string()),
+        age: None,
+    };
+
+    // `email` is left alone, `age` is set
+    update!(user: &mut user, age: 36);
+    assert_eq!(user.email.as_deref(), Some("ada@example.com"));
+    assert_eq!(user.age, Some(36));
+
+    // both are cleared
+    update!(user: &mut user, email: null, age: None);
+    assert_eq!(user.email, None);
+    assert_eq!(user.age, None);
+    println!("{:?}", user);
+
+    // `call!` and `key?:` take the same values
+    optargs::call!(update, user: &mut user, email: "ada@lovelace.dev".to_string());
+    let age = Some(Some(37));
+    update!(user: &mut user, age?: age);
+    println!("{:?}", user);
+
+    assert_eq!(Patch!().email, None);
+    assert_eq!(Patch!(email: null).email, Some(None));
+    assert_eq!(Patch!(email: "a@b.c").email, Some(Some("a@b.c")));
+    assert_eq!(optargs::make!(Patch, email: null).email, Some(None));
+}
@@ -0,0 +1,64 @@
+//! `#[optfn(parse)]` generates `plot_from_str`, which calls `plot` with arguments written like a
+//! python call. Handy for REPLs and config files, where calls only show up as text.
+
+#[optargs::optfn(parse)]
+fn plot(
+    x: Vec<f32>,
+    title: Option<&str>,
+    #[optarg(default = 1.0)] scale: f32,
+    #[optarg(repeated)] tags: Vec<String>,
+    #[optarg(nullable)] legend: Option<Option<bool>>,
+) -> String {
+    let x: Vec<f32> = x.into_iter().map(|x| x * scale).collect();
+    format!(
+        "{}: {:?} tags={:?} legend={:?}",
+        title.unwrap_or("untitled"),
+        x,
+        tags,
+        legend
+    )
+}
+
+// values borrowed from the string can be handed back
+#[optargs::optfn(parse)]
+fn first<'a>(words: Vec<&'a str>, fallback: Option<&'a str>) -> &'a str {
+    words.first().copied().or(fallback).unwrap_or_default()
+}
+
+#[optargs::optfn(parse)]
+fn sum(#[optarg(default)] start: i64, #[optarg(rest)] values: Vec<i64>) -> i64 {
+    start + values.iter().sum::<i64>()
+}
+
+fn main() {
+    let lines = [
+        "x=[1, 2, 3], title='speed'",
+        "x=[1.5], scale=2, tags='a', tags=\"b, c\"",
+        "x=[], legend=None",
+        "x=[4], legend=True, title=None",
+    ];
+    for line in lines.iter() {
+        println!("{}", plot_from_str(line).unwrap());
+    }
+
+    assert_eq!(
+        first_from_str("words=[hello, 'big world']").unwrap(),
+        "hello"
+    );
+    assert_eq!(first_from_str("words=[], fallback=empty").unwrap(), "empty");
+    assert_eq!(sum_from_str("values=[1, 2, 3], start=10").unwrap(), 16);
+    assert_eq!(sum_from_str("").unwrap(), 0);
+
+    // mistakes come back as a ParseError
+    let errors = [
+        "title='no x'",
+        "x=[1], y=[2]",
+        "x=[1], x=[2]",
+        "x=[one]",
+        "x=[1], title='unclosed",
+        "x",
+    ];
+    for line in errors.iter() {
+        println!("{:<24} {}", line, plot_from_str(line).unwrap_err());
+    }
+}
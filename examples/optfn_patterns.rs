@@ -0,0 +1,37 @@
+//! Parameters can destructure their value, as long as they're given a key with `name`.
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Size {
+    w: f32,
+    h: f32,
+}
+
+#[optargs::optfn]
+fn draw(
+    #[optarg(name = "pos")] (x, y): (f32, f32),
+    #[optarg(name = "color")] [r, g, b]: [u8; 3],
+    #[optarg(name = "size", default)] Size { w, h }: Size,
+) -> String {
+    format!(
+        "{}x{} at ({}, {}) in #{:02x}{:02x}{:02x}",
+        w, h, x, y, r, g, b
+    )
+}
+
+fn main() {
+    assert_eq!(
+        draw!(pos: (1.0, 2.0), color: [255, 0, 0]),
+        "0x0 at (1, 2) in #ff0000"
+    );
+
+    let size = Size { w: 3.0, h: 4.0 };
+    let color = [0, 128, 255];
+    println!("{}", draw!(color, size, pos: (0.5, 0.5)));
+
+    // the Args struct uses the keys as field names
+    let args = DrawArgs {
+        size: Some(size),
+        ..Default::default()
+    };
+    println!("{}", draw!(pos: (0.0, 0.0), color: [0; 3], ..args));
+}
@@ -2,8 +2,33 @@
 //! The goal here is to demistify what gets generated by the macro.
 
 /// How you would go about using optfn
-#[optargs::optfn]
-fn example(a: i32, b: Option<&str>) {}
+#[optargs::optfn(doc = false)]
+fn example(a: i32, b: Option<&str>) {
+    println!("{} {:?}", a, b);
+}
+
+/// The validator is generated once, in a module named after the function
+mod example_src {
+    pub struct Validator<const A: bool>;
+    impl Validator<false> {
+        pub fn builder() -> Self {
+            Validator
+        }
+    }
+    impl Validator<true> {
+        pub fn build(self) {}
+    }
+    impl Validator<false> {
+        pub fn a(self) -> Validator<true> {
+            Validator
+        }
+    }
+    impl<const A: bool> Validator<A> {
+        pub fn b(self) -> Validator<A> {
+            self
+        }
+    }
+}
 
 /// A rough translation of what opftn generates
 #[doc(hidden)]
@@ -16,14 +41,7 @@ macro_rules! example_src {
             { $( example_src!(@setter_helper inners $key $key $($value)? ); )* }
 
             // Validate
-            struct Validator<const A: bool> {}
-            impl Validator<true> { fn validate(self) {} }
-            impl Validator<false> { fn a(self) -> Validator<true> { unsafe {std::mem::transmute(self)} } }
-            impl<const A: bool> Validator<A> { fn b(self) -> Validator<A> { self  }}
-
-            #[allow(unused_mut)]
-            let mut validator = Validator::<false> {};
-            validator $(.$key())* .validate();
+            example_src::Validator::builder() $(.$key())* .build();
             example(inners.0.unwrap(), inners.1)
         }
     };
@@ -0,0 +1,58 @@
+//! `#[optfn(register)]` makes a function callable by its name, with arguments that are only known
+//! at runtime. Values are passed as `Box<dyn Any>` and have to be the exact type of the parameter,
+//! borrowed ones with a `'static` lifetime.
+
+use optargs::registry::Args;
+use optargs::{ArgError, Registry};
+
+#[optargs::optfn(register)]
+fn plot(x: Vec<i32>, title: Option<&str>, #[optarg(default = 1.0)] scale: f32) -> String {
+    let x: Vec<f32> = x.into_iter().map(|x| x as f32 * scale).collect();
+    format!("{}: {:?}", title.unwrap_or("untitled"), x)
+}
+
+#[optargs::optfn(register)]
+fn sum(start: i64, #[optarg(rest)] values: Vec<i64>) -> i64 {
+    start + values.iter().sum::<i64>()
+}
+
+fn main() {
+    Registry::register(plot::REGISTRATION);
+    Registry::register(sum::REGISTRATION);
+    println!("registered: {:?}", Registry::names());
+
+    let args: Args = vec![
+        ("x", Box::new(vec![1, 2, 3])),
+        ("title", Box::new("speed")),
+        ("scale", Box::new(0.5f32)),
+    ];
+    let out = Registry::call("plot", args).unwrap();
+    println!("{}", out.downcast_ref::<String>().unwrap());
+
+    // the rest parameter is passed as a whole Vec
+    let args: Args = vec![
+        ("start", Box::new(10i64)),
+        ("values", Box::new(vec![1i64, 2, 3])),
+    ];
+    let out = Registry::call("sum", args).unwrap();
+    assert_eq!(out.downcast_ref::<i64>(), Some(&16));
+
+    // mistakes are caught at runtime instead
+    let missing = Registry::call("plot", Vec::new()).unwrap_err();
+    assert_eq!(
+        missing,
+        ArgError::Missing {
+            function: "plot",
+            key: "x"
+        }
+    );
+    println!("{}", missing);
+
+    let wrong: Args = vec![("x", Box::new(vec![1.0, 2.0]))];
+    println!("{}", Registry::call("plot", wrong).unwrap_err());
+
+    let unknown: Args = vec![("y", Box::new(1))];
+    println!("{}", Registry::call("plot", unknown).unwrap_err());
+
+    println!("{}", Registry::call("scatter", Vec::new()).unwrap_err());
+}
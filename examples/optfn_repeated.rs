@@ -0,0 +1,59 @@
+//! Keys marked `#[optarg(repeated)]` can be passed more than once, and collect every value into
+//! their `Vec` in call order. Passed next to `..args`, they replace the spread's `Vec`.
+
+#[optargs::optfn]
+fn chart(
+    title: &str,
+    #[optarg(repeated)] series: Vec<(&str, Vec<i32>)>,
+    #[optarg(repeated, name = "tag")] tags: Vec<String>,
+) -> String {
+    let names: Vec<&str> = series.iter().map(|(name, _)| *name).collect();
+    format!("{}: {:?} {:?}", title, names, tags)
+}
+
+#[derive(optargs::OptStruct)]
+struct Request {
+    url: &'static str,
+    #[optarg(repeated)]
+    header: Vec<(&'static str, &'static str)>,
+}
+
+fn main() {
+    let a = ("a", vec![1, 2]);
+    let out = chart!(
+        title: "sales",
+        series: a,
+        series: ("b", vec![3]),
+        tag: "q1".to_string(),
+        series: ("c", vec![]),
+    );
+    assert_eq!(out, r#"sales: ["a", "b", "c"] ["q1"]"#);
+    println!("{}", out);
+
+    // left out, it's empty
+    println!("{}", chart!(title: "empty"));
+
+    // `call!` takes one item per call too
+    println!(
+        "{}",
+        optargs::call!(chart, title: "t", tag: "x".into(), tag: "y".into())
+    );
+
+    let args = ChartArgs {
+        series: Some(vec![("old", vec![])]),
+        tags: Some(vec!["kept".to_string()]),
+        ..Default::default()
+    };
+    let out = chart!(title: "spread", series: ("new", vec![1]), ..args);
+    assert_eq!(out, r#"spread: ["new"] ["kept"]"#);
+
+    let request = Request!(
+        url: "https://example.com",
+        header: ("Accept", "text/html"),
+        header: ("User-Agent", "optargs"),
+    );
+    println!("{} {:?}", request.url, request.header);
+
+    let request = optargs::make!(Request, url: "/", header: ("A", "b"));
+    assert_eq!(request.header.len(), 1);
+}
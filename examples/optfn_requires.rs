@@ -0,0 +1,78 @@
+//! Optional arguments that only make sense together, or not at all.
+//!
+//! `requires` and `conflicts_with` are checked at compile time, and the error names both keys, ie
+//! "`ylabel` requires `y`" or "`log_scale` conflicts with `bar`". Spreading `..args` checks them
+//! at runtime instead, and panics with the same message. Partials and builders return it as an
+//! error.
+
+#[optargs::optfn]
+fn chart(
+    x: Vec<f64>,
+    y: Option<Vec<f64>>,
+    #[optarg(requires = "y")] ylabel: Option<&str>,
+    bar: Option<bool>,
+    #[optarg(conflicts_with = "bar")] log_scale: Option<bool>,
+) -> String {
+    let kind = match (bar, log_scale) {
+        (Some(true), _) => "bar",
+        (_, Some(true)) => "log",
+        _ => "line",
+    };
+    match (y, ylabel) {
+        (Some(y), Some(label)) => format!("{} chart of {:?} against {} {:?}", kind, x, label, y),
+        (Some(y), None) => format!("{} chart of {:?} against {:?}", kind, x, y),
+        (None, _) => format!("{} chart of {:?}", kind, x),
+    }
+}
+
+#[derive(Debug, optargs::OptStruct)]
+#[optstruct(builder)]
+struct Axis {
+    #[optarg(default = 0.0)]
+    min: f64,
+    max: Option<f64>,
+    #[optarg(requires = "max", conflicts_with = "auto")]
+    ticks: Option<usize>,
+    auto: Option<bool>,
+}
+
+fn main() {
+    println!(
+        "{}",
+        chart!(x: vec![1.0, 2.0], y: vec![3.0, 4.0], ylabel: "speed")
+    );
+    println!("{}", chart!(x: vec![1.0], log_scale: true));
+    println!("{}", optargs::call!(chart, x: vec![1.0], bar: true));
+
+    // none of these compile, with these errors:
+    // chart!(x: vec![1.0], ylabel: "speed");
+    //     `ylabel` requires `y`
+    // chart!(x: vec![1.0], bar: true, log_scale: true);
+    //     `log_scale` conflicts with `bar`
+
+    let args = ChartArgs {
+        y: Some(vec![2.0]),
+        ..Default::default()
+    };
+    println!("{}", chart!(x: vec![1.0], ylabel: "speed", ..args));
+
+    // this one compiles, but panics once it runs, since the spread doesn't set `y`:
+    // chart!(x: vec![1.0], ylabel: "speed", ..ChartArgs::default());
+
+    let axis = Axis!(max: 10.0, ticks: 5);
+    println!(
+        "{} {:?} {:?} {:?}",
+        axis.min, axis.max, axis.ticks, axis.auto
+    );
+
+    let axis = optargs::make!(Axis, auto: true);
+    assert_eq!(axis.ticks, None);
+
+    // the builder checks the fields that are set when it's built, and returns the message
+    match Axis::builder().ticks(5).build() {
+        Err(optargs::BuildError::Rule(broken)) => println!("{}", broken),
+        other => panic!("expected a broken rule, got {:?}", other),
+    }
+    let axis = Axis::builder().max(10.0).ticks(5).build().unwrap();
+    assert_eq!(axis.ticks, Some(5));
+}
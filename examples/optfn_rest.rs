@@ -0,0 +1,43 @@
+//! `#[optarg(rest)]` collects any trailing positional arguments, like `*args` in python.
+
+#[derive(Debug)]
+enum Level {
+    Info,
+    Warn,
+}
+
+#[optargs::optfn]
+fn log(level: Level, prefix: Option<&str>, #[optarg(rest)] parts: Vec<String>) -> String {
+    format!("{:?} {}{}", level, prefix.unwrap_or(""), parts.join(" "))
+}
+
+#[optargs::optfn]
+fn total(#[optarg(rest)] values: impl IntoIterator<Item = i32>) -> i32 {
+    values.into_iter().sum()
+}
+
+fn main() {
+    let b = "b".to_string();
+    let c = "c".to_string();
+    assert_eq!(
+        log!(level: Level::Warn, "a".to_string(), b, c),
+        "Warn a b c"
+    );
+
+    // keys can still be shorthands, as long as they come before the positionals
+    let prefix = "> ";
+    println!("{}", log!(level: Level::Info, prefix, "hello".to_string()));
+
+    // the rest can be empty
+    println!("{}", log!(level: Level::Info));
+
+    // or come from a spread
+    let args = LogArgs {
+        parts: Some(vec!["from".to_string(), "args".to_string()]),
+        ..Default::default()
+    };
+    assert_eq!(log!(level: Level::Info, ..args), "Info from args");
+
+    assert_eq!(total!(1, 2, 3), 6);
+    assert_eq!(total!(), 0);
+}
@@ -0,0 +1,39 @@
+//! `#[optstruct(argv)]` turns an OptStruct into a small command-line parser.
+//!
+//! cargo run --example optstruct_argv -- --price 10.5 --doges 3 --to-the-moon
+
+/// Send GME to the moon
+#[derive(optargs::OptStruct)]
+#[optstruct(argv)]
+struct Gme {
+    /// The price per share
+    price: f32,
+
+    /// Fly to the moon
+    to_the_moon: Option<bool>,
+
+    /// Number of rockets to launch
+    #[optarg(default = 1)]
+    rocket_ships: usize,
+
+    /// Number of dogs on board
+    doges: Option<usize>,
+}
+
+fn main() {
+    let gme = Gme::from_argv(std::env::args()).unwrap_or_else(|e| e.exit());
+
+    println!(
+        "${} {} {} {}",
+        gme.price,
+        gme.to_the_moon.map(|_| "🌓").unwrap_or(""),
+        (0..gme.rocket_ships).map(|_| "🚀").collect::<String>(),
+        (0..gme.doges.unwrap_or(0))
+            .map(|_| "🐶")
+            .collect::<String>(),
+    );
+
+    // the same struct can still be built with the macro
+    let gme = Gme! { price: 10.0, doges: 2 };
+    assert_eq!(gme.rocket_ships, 1);
+}
@@ -0,0 +1,45 @@
+//! `#[optstruct(builder)]` generates `Request::builder()`, a builder that only checks the required
+//! fields when it's built. It can be filled in from different branches and passed around, and
+//! `build` returns every missing field at once.
+
+#[derive(Debug, optargs::OptStruct)]
+#[optstruct(builder)]
+pub struct Request {
+    pub url: String,
+    pub method: &'static str,
+
+    #[optarg(default = 30)]
+    pub timeout: u32,
+
+    #[optarg(repeated)]
+    pub headers: Vec<(String, String)>,
+
+    pub body: Option<String>,
+}
+
+fn authorize(builder: RequestBuilder, token: Option<&str>) -> RequestBuilder {
+    match token {
+        Some(token) => builder.headers(("Authorization".to_string(), token.to_string())),
+        None => builder,
+    }
+}
+
+fn main() {
+    let upload = true;
+
+    let mut builder = Request::builder().url("https://example.com".to_string());
+    builder = match upload {
+        true => builder.method("POST").body("hello".to_string()),
+        false => builder.method("GET"),
+    };
+    let request = authorize(builder, Some("secret")).build().unwrap();
+    assert_eq!(request.timeout, 30);
+    println!("{:?}", request);
+
+    let err = Request::builder().timeout(5).build().unwrap_err();
+    match &err {
+        optargs::BuildError::Missing(missing) => assert_eq!(missing.fields, ["url", "method"]),
+        optargs::BuildError::Rule(_) => unreachable!(),
+    }
+    println!("{}", err);
+}
@@ -9,4 +9,5 @@ fn main() {
         a: 10,
         b: "asd".into()
     };
+    println!("{} {:?}", ex.a, ex.b);
 }
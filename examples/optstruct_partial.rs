@@ -0,0 +1,54 @@
+//! `#[optstruct(partial)]` generates `PartialServer`, with every field as an `Option`. Partials are
+//! layered with `merge`, where later layers win, and `finalize` checks the required fields.
+
+#[derive(Debug, optargs::OptStruct)]
+#[optstruct(partial)]
+pub struct Server {
+    pub host: String,
+    pub port: u16,
+
+    #[optarg(default = 4)]
+    pub workers: usize,
+
+    pub log: Option<String>,
+}
+
+// a layer read from somewhere, ie `PORT=8080`
+fn from_env(vars: &[(&str, &str)]) -> PartialServer {
+    let mut layer = PartialServer::default();
+    for (key, value) in vars {
+        match *key {
+            "HOST" => layer.host = Some(value.to_string()),
+            "PORT" => layer.port = value.parse().ok(),
+            "WORKERS" => layer.workers = value.parse().ok(),
+            _ => {}
+        }
+    }
+    layer
+}
+
+fn main() {
+    let defaults = PartialServer {
+        host: Some("localhost".to_string()),
+        ..Default::default()
+    };
+    let env = from_env(&[("PORT", "8080")]);
+    let cli = PartialServer {
+        port: Some(9000),
+        log: Some("debug".to_string()),
+        ..Default::default()
+    };
+
+    let server = defaults.merge(env).merge(cli).finalize().unwrap();
+    assert_eq!(server.port, 9000);
+    assert_eq!(server.workers, 4);
+    println!("{:?}", server);
+
+    // every missing field is reported at once
+    let err = PartialServer::default().finalize().unwrap_err();
+    match &err {
+        optargs::BuildError::Missing(missing) => assert_eq!(missing.fields, ["host", "port"]),
+        optargs::BuildError::Rule(_) => unreachable!(),
+    }
+    println!("{}", err);
+}
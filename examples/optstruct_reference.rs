@@ -1,11 +1,12 @@
 //! poc for struct builder
 
+#[allow(dead_code)]
 struct Example {
     a: i32,
     b: Option<&'static str>,
 }
 impl Example {
-    fn build(mut self) -> bool {
+    fn build(self) -> bool {
         false
     }
 }
@@ -15,7 +16,7 @@ impl Example {
 macro_rules! Example {
     ($($key:ident $(: $value:expr)? ), *) => {{
         let mut inners = (None, None);
-        { $( Example!(@setter_helper inners $key $key $($value)? ) )* }
+        { $( Example!(@setter_helper inners $key $key $($value)? ); )* }
         Example {
             a: inners.0.unwrap(),
             b: inners.1
@@ -40,4 +41,5 @@ macro_rules! Example {
 
 fn main() {
     let p = Example! { a: 10 };
+    println!("{}", p.build());
 }
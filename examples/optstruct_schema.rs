@@ -0,0 +1,44 @@
+//! `#[optstruct(json_schema)]` generates `Scatter::json_schema()`, a JSON Schema for config files
+//! that's built from the struct itself, so the two can't drift apart.
+
+use std::collections::HashMap;
+
+/// A scatter plot
+#[derive(optargs::OptStruct)]
+#[optstruct(json_schema)]
+pub struct Scatter {
+    /// The x coordinates
+    pub x: Vec<f64>,
+
+    /// The y coordinates, `x` itself when left out
+    #[optarg(name = "points")]
+    pub y: Option<Vec<f64>>,
+
+    /// Shown above the plot
+    #[optarg(default = "untitled".to_string())]
+    pub title: String,
+
+    #[optarg(default = 2)]
+    pub size: u32,
+
+    #[optarg(default = vec![0.5, -1.0])]
+    pub margins: Vec<f32>,
+
+    #[optarg(default)]
+    pub filled: bool,
+
+    #[optarg(nullable)]
+    pub legend: Option<Option<bool>>,
+
+    pub labels: Option<HashMap<String, String>>,
+}
+
+fn main() {
+    let schema = Scatter::json_schema();
+    println!("{}", schema);
+
+    assert!(schema.contains(r#""default": "untitled""#));
+
+    // like serde, `Option` fields can be null as well as left out
+    assert_eq!(schema.matches(r#""type": "null""#).count(), 3);
+}
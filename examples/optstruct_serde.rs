@@ -0,0 +1,64 @@
+//! With `#[optstruct(deserialize)]` and the "serde" feature, OptStruct also derives a Deserialize
+//! that follows the same rules as the macro: required fields must be present, optionals and
+//! defaults may be left out. `PartialScatter` deserializes too, with every key optional.
+//!
+//! cargo run --example optstruct_serde --features serde
+
+#[derive(Debug, optargs::OptStruct)]
+#[optstruct(partial, deserialize)]
+struct Scatter<'a> {
+    x: Vec<i32>,
+
+    #[optarg(name = "points")]
+    y: Option<Vec<i32>>,
+
+    title: Option<&'a str>,
+
+    #[optarg(default = 1.0)]
+    scale: f32,
+
+    #[optarg(default)]
+    legend: bool,
+
+    // `null` is kept apart from a missing key
+    #[optarg(nullable)]
+    color: Option<Option<&'a str>>,
+}
+
+fn main() {
+    let from_macro = Scatter! {
+        x: vec![1, 2, 3],
+        points: vec![4, 5, 6],
+        title: "scatter"
+    };
+
+    let from_json: Scatter =
+        serde_json::from_str(r#"{ "x": [1, 2, 3], "points": [4, 5, 6], "title": "scatter" }"#)
+            .unwrap();
+
+    assert_eq!(format!("{:?}", from_macro), format!("{:?}", from_json));
+    assert_eq!(from_json.scale, 1.0);
+    assert!(!from_json.legend);
+    assert_eq!(from_json.color, None);
+
+    let cleared: Scatter = serde_json::from_str(r#"{ "x": [], "color": null }"#).unwrap();
+    assert_eq!(cleared.color, Some(None));
+
+    // required fields are still required
+    let missing = serde_json::from_str::<Scatter>(r#"{ "title": "scatter" }"#);
+    println!("{}", missing.unwrap_err());
+
+    // a file that only sets some keys, layered over the ones set in code
+    let file: PartialScatter =
+        serde_json::from_str(r#"{ "title": "from file", "scale": 2.0 }"#).unwrap();
+    let base = PartialScatter {
+        x: Some(vec![7, 8]),
+        title: Some("from code"),
+        ..Default::default()
+    };
+    let layered = base.merge(file).finalize().unwrap();
+    assert_eq!(layered.title, Some("from file"));
+    assert_eq!(layered.scale, 2.0);
+
+    println!("{:?} {:?} {:?}", from_json.x, from_json.y, from_json.title);
+}
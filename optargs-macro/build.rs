@@ -0,0 +1,28 @@
+//! `#[diagnostic::on_unimplemented]` only exists since Rust 1.78, and older compilers reject the
+//! whole `diagnostic` namespace. Since the macros run on the same compiler as the code they expand
+//! into, it's enough to check the version once here.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=RUSTC");
+    let minor = minor_version().unwrap_or(0);
+
+    // older cargos warn about the instruction instead of ignoring it
+    if minor >= 80 {
+        println!("cargo:rustc-check-cfg=cfg(optargs_diagnostic)");
+    }
+    if minor >= 78 {
+        println!("cargo:rustc-cfg=optargs_diagnostic");
+    }
+}
+
+// the 78 of "rustc 1.78.0 (9b00956e5 2024-04-29)"
+fn minor_version() -> Option<u32> {
+    let rustc = env::var_os("RUSTC")?;
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    let mut parts = version.strip_prefix("rustc 1.")?.split('.');
+    parts.next()?.parse().ok()
+}
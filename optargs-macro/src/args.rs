@@ -0,0 +1,231 @@
+//! The `PlotArgs` struct generated next to every optfn.
+//!
+//! It holds every argument of the function as an `Option`, so a (partial) set of arguments can be
+//! stored, passed around, and spread into a call with `plot!(x: v, ..args)`.
+
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::visit_mut::{self, VisitMut};
+use syn::{GenericParam, Generics, Ident, Lifetime, ReturnType, Type, TypeReference};
+
+use crate::field::BuilderField;
+use crate::optfn::OptFn;
+
+// PlotArgs for `plot`, GoGmeArgs for `go_gme`
+pub fn args_ident(name: &Ident) -> Ident {
+    camel_ident(name, "Args")
+}
+
+// `name` in CamelCase, followed by `suffix`
+pub fn camel_ident(name: &Ident, suffix: &str) -> Ident {
+    let camel: String = name
+        .to_string()
+        .trim_start_matches("r#")
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+
+    format_ident!("{}{}", camel, suffix, span = name.span())
+}
+
+/// The arguments' types as they appear in the Args struct, with elided lifetimes named `'args`
+/// and `impl Trait` turned into generic parameters.
+pub struct Named {
+    // the generics of the function, plus `'args` if any lifetime was elided and one parameter
+    // for every `impl Trait`
+    pub generics: Generics,
+
+    // required arguments first, then optional ones, like the fields of OptFn
+    pub types: Vec<Type>,
+
+    // a single item of the rest argument
+    pub rest: Option<Type>,
+
+    // the whole collection of the extra argument
+    pub extra: Option<Type>,
+
+    // the return type, which borrows from `'args` if it elides a lifetime
+    pub output: ReturnType,
+}
+
+pub fn named(opt: &OptFn) -> Named {
+    // Elided lifetimes are fine in a function signature but not in a struct, so give them a name
+    let lifetime = Lifetime::new("'args", Span::call_site());
+    let mut elided = NameElided {
+        lifetime: lifetime.clone(),
+        found: false,
+    };
+
+    let mut impls = ImplParams(Vec::new());
+
+    let mut name = |ty: &Type| {
+        let mut ty = ty.clone();
+        elided.visit_type_mut(&mut ty);
+        impls.visit_type_mut(&mut ty);
+        ty
+    };
+
+    let types = opt
+        .required_args
+        .iter()
+        .chain(opt.optional_args.iter())
+        .map(|BuilderField { ty, .. }| name(ty))
+        .collect();
+    let rest = opt.rest.as_ref().map(|BuilderField { ty, .. }| name(ty));
+    let extra = opt.extra.as_ref().map(|BuilderField { ty, .. }| name(ty));
+
+    let mut output = opt.original.sig.output.clone();
+    if elided.found {
+        elided.visit_return_type_mut(&mut output);
+    }
+
+    let mut generics: Generics = opt.original.sig.generics.clone();
+    if elided.found {
+        generics.params.insert(0, syn::parse_quote! { #lifetime });
+    }
+    generics.params.extend(impls.0);
+
+    Named {
+        generics,
+        types,
+        rest,
+        extra,
+        output,
+    }
+}
+
+pub fn generate(opt: &OptFn) -> TokenStream2 {
+    let OptFn {
+        original,
+        required_args,
+        optional_args,
+        rest,
+        extra,
+        name,
+        ..
+    } = opt;
+
+    let args_name = args_ident(name);
+    let vis = &original.vis;
+    let Named {
+        generics,
+        types,
+        rest: rest_ty,
+        extra: extra_ty,
+        ..
+    } = named(opt);
+
+    let mut fields = TokenStream2::new();
+    let mut defaults = TokenStream2::new();
+    for (BuilderField { ident, .. }, ty) in
+        required_args.iter().chain(optional_args.iter()).zip(&types)
+    {
+        fields.extend(quote! { pub #ident: ::core::option::Option<#ty>, });
+        defaults.extend(quote! { #ident: ::core::option::Option::None, });
+    }
+    if let (Some(BuilderField { ident, .. }), Some(ty)) = (extra, extra_ty) {
+        fields.extend(quote! { pub #ident: ::core::option::Option<#ty>, });
+        defaults.extend(quote! { #ident: ::core::option::Option::None, });
+    }
+    if let (Some(BuilderField { ident, .. }), Some(ty)) = (rest, rest_ty) {
+        fields.extend(quote! { pub #ident: ::core::option::Option<::std::vec::Vec<#ty>>, });
+        defaults.extend(quote! { #ident: ::core::option::Option::None, });
+    }
+
+    // Generic parameters that only show up in the return type still need to be used somewhere
+    let markers: Vec<TokenStream2> = generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(ty) => {
+                let ident = &ty.ident;
+                Some(quote! { fn() -> #ident })
+            }
+            GenericParam::Lifetime(lt) => {
+                let lt = &lt.lifetime;
+                Some(quote! { &#lt () })
+            }
+            GenericParam::Const(_) => None,
+        })
+        .collect();
+    if !markers.is_empty() {
+        fields.extend(quote! {
+            #[doc(hidden)]
+            pub __marker: ::core::marker::PhantomData<(#(#markers,)*)>,
+        });
+        defaults.extend(quote! { __marker: ::core::marker::PhantomData, });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let doc = format!(
+        "The arguments of [`{0}`], any of which may be missing.\n\nSpread them into a call with `{0}!(..args)`, where keys passed next to it win.",
+        name
+    );
+
+    quote! {
+        #[doc = #doc]
+        #vis struct #args_name #impl_generics #where_clause {
+            #fields
+        }
+
+        impl #impl_generics ::core::default::Default for #args_name #ty_generics #where_clause {
+            fn default() -> Self {
+                Self { #defaults }
+            }
+        }
+    }
+}
+
+// `impl Trait` can't be the type of a field, so every one becomes a generic parameter
+struct ImplParams(Vec<GenericParam>);
+
+impl VisitMut for ImplParams {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        match ty {
+            Type::ImplTrait(imp) => {
+                let ident = format_ident!("__Impl{}", self.0.len());
+                let bounds = &imp.bounds;
+                self.0.push(syn::parse_quote! { #ident: #bounds });
+                *ty = syn::parse_quote! { #ident };
+            }
+            _ => visit_mut::visit_type_mut(self, ty),
+        }
+    }
+}
+
+struct NameElided {
+    lifetime: Lifetime,
+    found: bool,
+}
+
+impl VisitMut for NameElided {
+    fn visit_type_reference_mut(&mut self, r: &mut TypeReference) {
+        if r.lifetime.is_none() {
+            r.lifetime = Some(self.lifetime.clone());
+            self.found = true;
+        }
+        visit_mut::visit_type_reference_mut(self, r);
+    }
+
+    fn visit_lifetime_mut(&mut self, l: &mut Lifetime) {
+        if l.ident == "_" {
+            *l = self.lifetime.clone();
+            self.found = true;
+        }
+    }
+
+    // `impl Fn(&str)` and `fn(&str)` have their own elision rules
+    fn visit_type_bare_fn_mut(&mut self, _: &mut syn::TypeBareFn) {}
+    fn visit_parenthesized_generic_arguments_mut(
+        &mut self,
+        _: &mut syn::ParenthesizedGenericArguments,
+    ) {
+    }
+}
@@ -0,0 +1,109 @@
+//! `#[optstruct(argv)]` generates `Struct::from_argv(std::env::args())`.
+//!
+//! Every field becomes a `--flag`, with underscores turned into dashes. Required fields are
+//! required flags, optional fields are optional flags, and optional `bool`s are switches.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::Type;
+
+use crate::field::BuilderField;
+use crate::optstruct::OptStruct;
+
+pub fn generate(opt: &OptStruct) -> TokenStream2 {
+    let OptStruct {
+        name,
+        generics,
+        required_args,
+        optional_args,
+        doc,
+        ..
+    } = opt;
+
+    let fields = required_args
+        .iter()
+        .map(|f| (true, f))
+        .chain(optional_args.iter().map(|f| (false, f)));
+
+    let mut flags = TokenStream2::new();
+    let mut conversions = TokenStream2::new();
+
+    for (id, (required, field)) in fields.enumerate() {
+        let BuilderField {
+            ident,
+            key,
+            ty,
+            attrs,
+        } = field;
+
+        let flag = key.to_string().trim_start_matches("r#").replace('_', "-");
+        let value = key.to_string().trim_start_matches("r#").to_uppercase();
+        let switch = !required && is_bool(ty);
+        let help = attrs.doc.lines().next().unwrap_or_default();
+
+        flags.extend(quote! {
+            ::optargs::argv::Flag {
+                name: #flag,
+                value: #value,
+                switch: #switch,
+                required: #required,
+                help: #help,
+            },
+        });
+
+        let value = quote! { values.next().flatten() };
+        conversions.extend(match required {
+            true => quote! {
+                #ident: ::optargs::argv::required::<#ty>(&FLAGS[#id], #value)?,
+            },
+            false => {
+                let value = field.resolve_optional(quote! {
+                    ::optargs::argv::optional::<#ty>(&FLAGS[#id], #value)?
+                });
+                quote! { #ident: #value, }
+            }
+        });
+    }
+
+    let rules = opt.rules.check(
+        optional_args,
+        |field| {
+            let id = optional_args.iter().position(|f| f.key == field.key);
+            let id = required_args.len() + id.unwrap();
+            quote! { values[#id].is_some() }
+        },
+        |message| quote! { return ::core::result::Result::Err(::optargs::argv::ArgvError::Rule(#message)); },
+    );
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Build this struct from command-line arguments, ie `std::env::args()`.
+            ///
+            /// Values are parsed with `FromStr`. Passing `--help` returns `ArgvError::Help` with
+            /// the generated help text.
+            pub fn from_argv<I, S>(args: I) -> ::core::result::Result<Self, ::optargs::argv::ArgvError>
+            where
+                I: ::core::iter::IntoIterator<Item = S>,
+                S: ::core::convert::Into<::std::string::String>,
+            {
+                const FLAGS: &[::optargs::argv::Flag] = &[ #flags ];
+
+                let values = ::optargs::argv::parse(#doc, FLAGS, args)?;
+                #rules
+
+                let mut values = values.into_iter();
+
+                ::core::result::Result::Ok(#name { #conversions })
+            }
+        }
+    }
+}
+
+fn is_bool(ty: &Type) -> bool {
+    match ty {
+        Type::Path(p) => p.qself.is_none() && p.path.is_ident("bool"),
+        _ => false,
+    }
+}
@@ -0,0 +1,345 @@
+//! A builder that carries the values of the arguments, used by `optargs::call!` and `optargs::make!`.
+//!
+//! The generated `plot!` macro only works after its declaration, since that's how macro_rules
+//! works. `call!(plot, x: v)` instead expands to ordinary method calls:
+//!
+//! ```text
+//! plot::Validator::__builder().x(v).__call()
+//! ```
+//!
+//! which go through normal path resolution. Like the validator, the builder tracks which required
+//! arguments were passed in its markers, see `crate::validator`, so `__call` only exists once all
+//! of them were.
+//!
+//! The same builder is public as `PlotBuilder`, started with `plot::builder()` and finished with
+//! `.call()`, so a call can be put together across branches and helpers before it runs.
+//!
+//! `#[optstruct(builder)]` reuses it without any markers, as a public builder that checks the
+//! required fields at runtime instead.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{GenericParam, Generics, Ident, LitStr, Type, Visibility};
+
+use crate::validator::GenericGenerator;
+
+pub struct Builder<'a> {
+    pub vis: &'a Visibility,
+
+    // the name of the builder type
+    pub name: Ident,
+
+    // the docs of the builder type, which is hidden unless it's meant to be used directly
+    pub doc: TokenStream2,
+
+    // the generics of `storage`
+    pub generics: &'a Generics,
+
+    // the type holding the values, and an empty one
+    pub storage: TokenStream2,
+    pub init: TokenStream2,
+
+    // the setters with a marker: the required arguments, then the last `tracked` ones are
+    // optional arguments in a rule, see `crate::rules`
+    pub required: Vec<Setter>,
+    pub tracked: usize,
+    pub optional: Vec<Setter>,
+
+    // methods available no matter which arguments were passed
+    pub extra: TokenStream2,
+
+    // methods available once all the required arguments were passed
+    pub finish: TokenStream2,
+}
+
+pub struct Setter {
+    pub key: Ident,
+    pub ty: Type,
+
+    // the field of the storage holding the value
+    pub field: TokenStream2,
+
+    // push `ty` into a Vec instead of replacing the value, for `#[optarg(repeated)]`
+    pub repeated: bool,
+
+    // take anything that converts into an Option<ty>, for `#[optarg(nullable)]`
+    pub nullable: bool,
+
+    // the note of `#[optarg(deprecated)]`, so `call!` warns like the macro does
+    pub deprecated: Option<LitStr>,
+
+    // swap `impl Trait` parameters of the builder for the type of the value, see `Retype`. Not
+    // for repeated or nullable setters, which can't tell the type from a single value
+    pub retype: Option<Retype>,
+}
+
+/*
+An omitted `Option<impl Trait>` leaves nothing to infer its parameter from, so the builder starts
+out with `Box<dyn Trait>` for it, like the macro's fallback. The setter then swaps the parameter
+for the type of its value, which means moving every other field into a new storage:
+
+    fn f<__Value0: Fn(i32) -> i32>(self, value: __Value0) -> PlotBuilder<'args, __Value0, M0> {
+        let inner = self.0;
+        PlotBuilder(PlotArgs { f: Some(value), x: inner.x, __marker: PhantomData })
+    }
+*/
+pub struct Retype {
+    // the parameters of the builder in `ty`, the fresh ones `ty` uses instead, and what they
+    // start out as
+    pub params: Vec<(Ident, Ident, Type)>,
+
+    // the storage without its generics, and every other one of its fields, moved out of `inner`
+    pub storage: TokenStream2,
+    pub moved: TokenStream2,
+}
+
+impl Setter {
+    fn deprecation(&self) -> TokenStream2 {
+        match &self.deprecated {
+            Some(note) => quote! { #[deprecated(note = #note)] },
+            None => quote! {},
+        }
+    }
+
+    // the generics, type and conversion of the setter's argument
+    fn param(&self) -> (TokenStream2, TokenStream2, TokenStream2) {
+        let ty = &self.ty;
+        match self.nullable {
+            true => (
+                quote! { <__Marker> },
+                quote! { impl ::optargs::__private::IntoNullable<#ty, __Marker> },
+                quote! { ::optargs::__private::IntoNullable::into_nullable(value) },
+            ),
+            false => (quote! {}, quote! { #ty }, quote! { value }),
+        }
+    }
+}
+
+impl Builder<'_> {
+    pub fn generate(&self) -> TokenStream2 {
+        let Builder {
+            vis,
+            name,
+            doc,
+            generics,
+            storage,
+            init,
+            required,
+            optional,
+            extra,
+            finish,
+            ..
+        } = self;
+
+        let where_clause = &generics.where_clause;
+        let gen = self.generator();
+
+        let impl_generics = gen.gen_all_generic();
+        let impl_original = gen.gen_all_generic_original();
+        let start = gen.start();
+        let (impl_done, done) = gen.gen_done();
+        let any = gen.gen_any();
+        let marker_field = gen.marker_field();
+        let marker_value = gen.marker_value();
+
+        let mut setters = TokenStream2::new();
+        for (id, setter) in required.iter().enumerate() {
+            if let Some(retype) = &setter.retype {
+                let (impl_generics, ty_gen_in) = gen.gen_marking(id);
+                let retyped = self.retyped(retype);
+                let ty_gen_out = self.generator_for(&retyped).gen_marked(id);
+                let method = self.retyping_setter(setter, retype, ty_gen_out);
+                setters.extend(quote! {
+                    #[allow(dead_code)]
+                    impl #impl_generics #name #ty_gen_in #where_clause {
+                        #method
+                    }
+                });
+                continue;
+            }
+
+            let Setter { key, field, .. } = setter;
+            let (generics, ty, value) = setter.param();
+            let deprecation = setter.deprecation();
+            let (impl_generics, ty_gen_in) = gen.gen_marking(id);
+            let ty_gen_out = gen.gen_marked(id);
+            setters.extend(quote! {
+                #[allow(dead_code)]
+                impl #impl_generics #name #ty_gen_in #where_clause {
+                    #[allow(clippy::wrong_self_convention)]
+                    #deprecation
+                    #vis fn #key #generics (self, value: #ty) -> #name #ty_gen_out {
+                        let mut inner = self.0;
+                        inner.#field = ::core::option::Option::Some(#value);
+                        #name(inner #marker_value)
+                    }
+                }
+            });
+        }
+
+        let mut optionals = TokenStream2::new();
+        for setter in optional {
+            if let Some(retype) = &setter.retype {
+                let retyped = self.retyped(retype);
+                let ty_gen_out = self.generator_for(&retyped).gen_any();
+                optionals.extend(self.retyping_setter(setter, retype, ty_gen_out));
+                continue;
+            }
+
+            let Setter {
+                key,
+                field,
+                repeated,
+                ..
+            } = setter;
+            let (generics, ty, value) = setter.param();
+            let deprecation = setter.deprecation();
+            let store = match repeated {
+                true => quote! {
+                    self.0.#field.get_or_insert_with(::std::vec::Vec::new).push(value);
+                },
+                false => quote! { self.0.#field = ::core::option::Option::Some(#value); },
+            };
+            optionals.extend(quote! {
+                #[allow(clippy::wrong_self_convention)]
+                #deprecation
+                #vis fn #key #generics (mut self, value: #ty) -> Self {
+                    #store
+                    self
+                }
+            });
+        }
+
+        quote! {
+            #doc
+            #[allow(dead_code, non_camel_case_types)]
+            #vis struct #name #impl_generics (#storage #marker_field) #where_clause;
+
+            #[allow(dead_code)]
+            impl #impl_original #name #start #where_clause {
+                #[doc(hidden)]
+                #vis fn __new() -> Self {
+                    #name(#init #marker_value)
+                }
+            }
+
+            #setters
+
+            #[allow(dead_code)]
+            impl #impl_generics #name #any #where_clause {
+                #optionals
+                #extra
+            }
+
+            #[allow(dead_code)]
+            impl #impl_done #name #done #where_clause {
+                #finish
+            }
+        }
+    }
+
+    // the builder before any argument has been passed, with every retyped parameter at its
+    // fallback, and the generics left to it
+    pub fn start(&self) -> (TokenStream2, Generics) {
+        let name = &self.name;
+        let fallbacks: Vec<_> = self
+            .required
+            .iter()
+            .chain(self.optional.iter())
+            .filter_map(|setter| setter.retype.as_ref())
+            .flat_map(|retype| retype.params.iter())
+            .collect();
+        let fallback = |ident: &Ident| {
+            fallbacks
+                .iter()
+                .find(|(param, ..)| param == ident)
+                .map(|(.., ty)| ty)
+        };
+
+        let mut args = TokenStream2::new();
+        let mut generics = self.generics.clone();
+        generics.params = Punctuated::new();
+        for param in self.generics.params.iter() {
+            match param {
+                GenericParam::Type(ty) => {
+                    let ident = &ty.ident;
+                    match fallback(ident) {
+                        Some(fallback) => args.extend(quote! { #fallback, }),
+                        None => {
+                            args.extend(quote! { #ident, });
+                            generics.params.push(param.clone());
+                        }
+                    }
+                }
+                GenericParam::Lifetime(lt) => {
+                    let lt = &lt.lifetime;
+                    args.extend(quote! { #lt, });
+                    generics.params.push(param.clone());
+                }
+                GenericParam::Const(c) => {
+                    let ident = &c.ident;
+                    args.extend(quote! { #ident, });
+                    generics.params.push(param.clone());
+                }
+            }
+        }
+        let markers = self.generator().start_markers();
+
+        (quote! { #name <#args #markers> }, generics)
+    }
+
+    // the generics of the builder, with the retyped parameters renamed to the setter's
+    fn retyped(&self, retype: &Retype) -> Generics {
+        let mut generics = self.generics.clone();
+        for param in generics.params.iter_mut() {
+            if let GenericParam::Type(ty) = param {
+                if let Some((_, fresh, _)) = retype.params.iter().find(|(p, ..)| *p == ty.ident) {
+                    ty.ident = fresh.clone();
+                }
+            }
+        }
+        generics
+    }
+
+    // a setter that hands back the builder with its `impl Trait` parameters swapped
+    fn retyping_setter(
+        &self,
+        setter: &Setter,
+        retype: &Retype,
+        ty_gen_out: TokenStream2,
+    ) -> TokenStream2 {
+        let Builder { vis, name, .. } = self;
+        let Setter { key, ty, field, .. } = setter;
+        let Retype { storage, moved, .. } = retype;
+
+        let fresh = self
+            .generics
+            .type_params()
+            .filter_map(|ty| {
+                let (_, fresh, _) = retype.params.iter().find(|(p, ..)| *p == ty.ident)?;
+                let bounds = &ty.bounds;
+                Some(quote! { #fresh: #bounds, })
+            })
+            .collect::<TokenStream2>();
+        let deprecation = setter.deprecation();
+        let marker_value = self.generator().marker_value();
+
+        quote! {
+            #[allow(clippy::wrong_self_convention)]
+            #deprecation
+            #vis fn #key <#fresh> (self, value: #ty) -> #name #ty_gen_out {
+                let inner = self.0;
+                #name(#storage { #field: ::core::option::Option::Some(value), #moved } #marker_value)
+            }
+        }
+    }
+
+    pub fn generator(&self) -> GenericGenerator<'_> {
+        self.generator_for(self.generics)
+    }
+
+    fn generator_for<'g>(&self, generics: &'g Generics) -> GenericGenerator<'g> {
+        GenericGenerator::new(self.required.len(), generics).tracking(self.tracked)
+    }
+}
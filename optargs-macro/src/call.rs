@@ -0,0 +1,141 @@
+//! `optargs::call!(plot, x: v)` and `optargs::make!(Scatter, x: v)`.
+//!
+//! Unlike the generated `plot!` and `Scatter!`, these find their target through ordinary path
+//! resolution, so they work before the declaration, across modules, and with `use` imports. They
+//! expand to the builder from `crate::builder`:
+//!
+//! ```text
+//! plot::Validator::__builder().x(v).__call()
+//! Scatter::__optargs_builder().x(v).__build()
+//! ```
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned, ToTokens};
+use syn::parse::ParseStream;
+use syn::spanned::Spanned;
+use syn::{Error, Expr, Ident, Path, Result, Token};
+
+pub struct Call {
+    target: Path,
+    keys: Vec<(Ident, Expr)>,
+    positional: Vec<Expr>,
+
+    // `make!` builds a struct, `call!` calls a function
+    make: bool,
+}
+
+impl Call {
+    pub fn parse_call(input: ParseStream) -> Result<Self> {
+        Self::parse_args(input, false)
+    }
+
+    pub fn parse_make(input: ParseStream) -> Result<Self> {
+        Self::parse_args(input, true)
+    }
+
+    fn parse_args(input: ParseStream, make: bool) -> Result<Self> {
+        let target: Path = input.parse()?;
+
+        let mut keys = Vec::new();
+        let mut positional = Vec::new();
+
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            if input.peek(Token![..]) {
+                return Err(input.error(
+                    "spreading `..args` is only supported by the generated macro, ie `plot!(..args)`",
+                ));
+            }
+
+            // `key: value` and the shorthand `key` are keys, everything else is positional
+            let keyed = input.peek2(Token![:]) && !input.peek2(Token![::]);
+            let shorthand = input.peek2(Token![,]) || is_last(input);
+
+            match input.peek(Ident) && (keyed || shorthand) {
+                true => {
+                    let key: Ident = input.parse()?;
+                    if !positional.is_empty() {
+                        return Err(Error::new_spanned(
+                            key,
+                            "keys must come before positional arguments",
+                        ));
+                    }
+                    let value = match input.parse::<Option<Token![:]>>()? {
+                        Some(_) => input.parse()?,
+                        None => syn::parse_quote! { #key },
+                    };
+                    keys.push((key, value));
+                }
+                false => positional.push(input.parse()?),
+            }
+        }
+
+        if make && !positional.is_empty() {
+            return Err(Error::new_spanned(
+                &positional[0],
+                "structs don't take positional arguments",
+            ));
+        }
+
+        Ok(Self {
+            target,
+            keys,
+            positional,
+            make,
+        })
+    }
+}
+
+fn is_null(value: &Expr) -> bool {
+    matches!(value, Expr::Path(p) if p.qself.is_none() && p.path.is_ident("null"))
+}
+
+// Whether there's a single token left, ie a shorthand key at the end of the call
+fn is_last(input: ParseStream) -> bool {
+    let fork = input.fork();
+    fork.parse::<Ident>().is_ok() && fork.is_empty()
+}
+
+impl ToTokens for Call {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let Call {
+            target,
+            keys,
+            positional,
+            make,
+        } = self;
+
+        // `<Tagged>::` would need the generics spelled out, `Tagged::` lets them be inferred
+        let explicit = target.segments.iter().any(|s| !s.arguments.is_empty());
+        let start = match (make, explicit) {
+            (true, true) => quote! { <#target>::__optargs_builder() },
+            (true, false) => quote! { #target::__optargs_builder() },
+            (false, _) => quote! { #target::Validator::__builder() },
+        };
+
+        // point errors about unknown or missing keys at the key itself
+        // `key: null` is spelled out, since nullable keys can't be told apart here
+        let setters = keys.iter().map(|(key, value)| match is_null(value) {
+            true => quote_spanned! { key.span()=> .#key(::optargs::Null) },
+            false => quote_spanned! { key.span()=> .#key(#value) },
+        });
+
+        let rest = match positional.is_empty() {
+            true => quote! {},
+            false => quote! { .__rest(::std::vec![#(#positional),*]) },
+        };
+
+        let finish = match make {
+            true => quote_spanned! { target.span()=> .__build() },
+            false => quote_spanned! { target.span()=> .__call() },
+        };
+
+        tokens.extend(quote! {
+            #start #(#setters)* #rest #finish
+        });
+    }
+}
@@ -0,0 +1,197 @@
+//! Deserialize support for OptStruct, enabled with `#[optstruct(deserialize)]` and the "serde"
+//! feature of optargs.
+//!
+//! We don't want to reimplement serde's derive, so instead we generate a private shadow of the
+//! struct with the right `#[serde]` attributes for every field and derive on that. The real
+//! struct's impl just deserializes the shadow and moves the fields across.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::visit::{self, Visit};
+use syn::visit_mut::VisitMut;
+use syn::{GenericParam, Ident, Lifetime, Type};
+
+use crate::field::BuilderField;
+use crate::optstruct::OptStruct;
+
+pub fn generate(opt: &OptStruct) -> TokenStream2 {
+    shadowed(opt, &opt.name, false)
+}
+
+// `PartialScatter` from `#[optstruct(partial)]`, where every key may be left out and defaults are
+// left for `finalize`
+pub fn generate_partial(opt: &OptStruct, partial: &Ident) -> TokenStream2 {
+    shadowed(opt, partial, true)
+}
+
+fn shadowed(opt: &OptStruct, target: &Ident, partial: bool) -> TokenStream2 {
+    let OptStruct {
+        name,
+        generics,
+        required_args,
+        optional_args,
+        ..
+    } = opt;
+
+    let shadow = format_ident!("__OptArgsShadow{}", target);
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+    let serde_crate = quote! { ::optargs::__private::serde };
+
+    let mut statics = false;
+    let mut defaults = TokenStream2::new();
+    let mut fields = TokenStream2::new();
+    let mut moves = TokenStream2::new();
+
+    for (required, field) in required_args
+        .iter()
+        .map(|f| (true, f))
+        .chain(optional_args.iter().map(|f| (false, f)))
+    {
+        let BuilderField {
+            ident, key, attrs, ..
+        } = field;
+        let key = key.to_string();
+        let mut ty = field_type(required, field, partial);
+
+        let mut serde_attrs = vec![quote! { rename = #key }];
+
+        // Option<T> fields are already treated as missing-is-none by serde
+        if let (Some(default), false) = (&attrs.default, partial) {
+            let default_fn = format_ident!("__optargs_default_{}", ident);
+            // the helper doesn't mention the struct's generics in its arguments, so name them
+            let params: Vec<String> = generics
+                .params
+                .iter()
+                .filter_map(|param| match param {
+                    GenericParam::Type(ty) => Some(ty.ident.to_string()),
+                    GenericParam::Const(c) => Some(c.ident.to_string()),
+                    GenericParam::Lifetime(_) => None,
+                })
+                .collect();
+            let default_path = match params.is_empty() {
+                true => default_fn.to_string(),
+                false => format!("{}::<{}>", default_fn, params.join(", ")),
+            };
+            let (impl_generics, _, where_clause) = generics.split_for_impl();
+            defaults.extend(quote! {
+                fn #default_fn #impl_generics () -> #ty #where_clause { #default }
+            });
+            serde_attrs.push(quote! { default = #default_path });
+        }
+
+        if attrs.nullable {
+            serde_attrs.push(quote! { default });
+            serde_attrs
+                .push(quote! { deserialize_with = "optargs::__private::deserialize_nullable" });
+        }
+
+        // `&'static str` can only be borrowed from input that lives forever, so the shadow
+        // borrows from its own lifetime instead, and the real struct requires `'de: 'static`
+        let mut replace = ReplaceStatic(false);
+        replace.visit_type_mut(&mut ty);
+        statics |= replace.0;
+
+        // serde only borrows plain `&str` and `&[u8]` on its own
+        if borrows(&ty) {
+            serde_attrs.push(quote! { borrow });
+        }
+
+        fields.extend(quote! {
+            #[serde( #(#serde_attrs),* )]
+            #ident: #ty,
+        });
+        moves.extend(quote! { #ident: shadow.#ident, });
+    }
+
+    // Mirror the bounds serde would place on the real struct
+    let mut de_generics = generics.clone();
+    let mut lifetimes: Vec<Lifetime> = generics.lifetimes().map(|l| l.lifetime.clone()).collect();
+    for param in de_generics.params.iter_mut() {
+        if let GenericParam::Type(ty) = param {
+            ty.bounds
+                .push(syn::parse_quote! { #serde_crate::Deserialize<'de> });
+        }
+    }
+    if statics {
+        lifetimes.push(syn::parse_quote! { 'static });
+    }
+    de_generics.params.insert(
+        0,
+        match lifetimes.is_empty() {
+            true => syn::parse_quote! { 'de },
+            false => syn::parse_quote! { 'de: #(#lifetimes)+* },
+        },
+    );
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+
+    let name_str = name.to_string();
+    let mut shadow_generics = generics.clone();
+    let mut shadow_args = generics.clone();
+    if statics {
+        shadow_generics
+            .params
+            .insert(0, syn::parse_quote! { '__optargs_static });
+        shadow_args.params.insert(0, syn::parse_quote! { 'de });
+    }
+    let (_, shadow_ty_generics, _) = shadow_args.split_for_impl();
+    let shadow_generics = &shadow_generics.params;
+
+    quote! {
+        ::optargs::__optargs_deserialize! {
+            const _: () = {
+                #[derive(#serde_crate::Deserialize)]
+                #[serde(crate = "optargs::__private::serde", rename = #name_str, deny_unknown_fields)]
+                struct #shadow <#shadow_generics> #where_clause {
+                    #fields
+                }
+
+                #defaults
+
+                impl #de_impl_generics #serde_crate::Deserialize<'de> for #target #ty_generics #where_clause {
+                    fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+                    where
+                        D: #serde_crate::Deserializer<'de>,
+                    {
+                        let shadow = <#shadow #shadow_ty_generics as #serde_crate::Deserialize>::deserialize(deserializer)?;
+                        ::core::result::Result::Ok(#target { #moves })
+                    }
+                }
+            };
+        }
+    }
+}
+
+// The type as declared on the original struct, or as an Option on the partial one
+fn field_type(required: bool, field: &BuilderField, partial: bool) -> Type {
+    let ty = &field.ty;
+    match !partial && (required || field.attrs.default.is_some()) {
+        true => (**ty).clone(),
+        false => syn::parse_quote! { ::core::option::Option<#ty> },
+    }
+}
+
+fn borrows(ty: &Type) -> bool {
+    struct FindLifetime(bool);
+    impl<'ast> Visit<'ast> for FindLifetime {
+        fn visit_lifetime(&mut self, l: &'ast Lifetime) {
+            self.0 |= l.ident != "static";
+            visit::visit_lifetime(self, l);
+        }
+    }
+
+    let mut finder = FindLifetime(false);
+    finder.visit_type(ty);
+    finder.0
+}
+
+// Swap 'static for the shadow's own lifetime, remembering whether there was one
+struct ReplaceStatic(bool);
+
+impl VisitMut for ReplaceStatic {
+    fn visit_lifetime_mut(&mut self, l: &mut Lifetime) {
+        if l.ident == "static" {
+            *l = syn::parse_quote! { '__optargs_static };
+            self.0 = true;
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Rustdoc for the generated macros.
+//!
+//! Without this, `plot!` shows up with no docs at all and users have to go read the signature of
+//! `plot` to figure out which keys exist. We list every key along with its type and whether it's
+//! required, and copy over the docs of the function or struct itself.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+
+use crate::field::BuilderField;
+
+pub struct MacroDoc<'a> {
+    // a line describing what the macro does, ie "Call `plot` with optional arguments."
+    pub summary: String,
+
+    // the docs of the function or struct
+    pub doc: &'a str,
+
+    pub required_args: &'a [BuilderField],
+    pub optional_args: &'a [BuilderField],
+    pub rest: Option<&'a BuilderField>,
+    pub extra: Option<&'a BuilderField>,
+
+    // whether spreading `..args` checks required arguments, groups or dependencies at runtime,
+    // which panics when they're missing or broken
+    pub spread_panics: bool,
+}
+
+impl MacroDoc<'_> {
+    pub fn generate(&self) -> TokenStream2 {
+        let mut out = self.summary.clone();
+
+        if !self.doc.is_empty() {
+            out += "\n\n";
+            out += self.doc;
+        }
+
+        out += "\n\n# Arguments\n";
+        let args = self
+            .required_args
+            .iter()
+            .map(|f| (true, f))
+            .chain(self.optional_args.iter().map(|f| (false, f)));
+
+        for (required, field) in args {
+            let status = match (required, &field.attrs.default) {
+                (true, _) => "required".to_string(),
+                (false, _) if field.attrs.repeated => "optional, can be repeated".to_string(),
+                (false, _) if field.attrs.nullable => "optional, can be `null`".to_string(),
+                (false, Some(default)) => {
+                    format!("optional, defaults to `{}`", pretty_expr(default))
+                }
+                (false, None) => "optional".to_string(),
+            };
+            out += &format!("\n- `{}: {}` ({})", field.key, pretty(&field.ty), status);
+
+            if let Some(note) = &field.attrs.deprecated {
+                out += &format!(" - **deprecated**: {}", note.value());
+            }
+            if let Some(env) = &field.attrs.env {
+                out += &format!(" - read from `${}` when left out", env.value());
+            }
+            if let Some(line) = field.attrs.doc.lines().next() {
+                out += &format!(" - {}", line);
+            }
+        }
+
+        if let Some(extra) = self.extra {
+            out += &format!(
+                "\n- any other key: collected into `{}: {}`",
+                extra.key,
+                pretty(&extra.ty)
+            );
+        }
+
+        if let Some(rest) = self.rest {
+            out += &format!(
+                "\n- `{}`: any trailing positional arguments, each a `{}`",
+                rest.key,
+                pretty(&rest.ty)
+            );
+        }
+
+        if self.spread_panics {
+            out += "\n\n# Panics\n\nWhen spreading `..args`, the required arguments and the groups and dependencies between the keys are only checked once the call runs, and a missing or broken one panics.";
+        }
+
+        quote! { #[doc = #out] }
+    }
+}
+
+/*
+The `ARGS` descriptor, with the same information as the docs of the macro:
+
+    &[::optargs::ArgInfo { name: "x", type_name: "Vec<i32>", required: true, default: None, doc: "" }]
+*/
+pub fn arg_infos(
+    required_args: &[BuilderField],
+    optional_args: &[BuilderField],
+    rest: Option<&BuilderField>,
+) -> TokenStream2 {
+    let args = required_args
+        .iter()
+        .map(|f| (true, f, pretty(&f.ty)))
+        .chain(optional_args.iter().map(|f| (false, f, pretty(&f.ty))))
+        .chain(rest.map(|f| (false, f, format!("Vec<{}>", pretty(&f.ty)))));
+
+    let infos = args.map(|(required, field, type_name)| {
+        let name = field.key.to_string().trim_start_matches("r#").to_string();
+        let default = match &field.attrs.default {
+            Some(default) => {
+                let default = pretty_expr(default);
+                quote! { ::core::option::Option::Some(#default) }
+            }
+            None => quote! { ::core::option::Option::None },
+        };
+        let doc = &field.attrs.doc;
+
+        quote! {
+            ::optargs::ArgInfo {
+                name: #name,
+                type_name: #type_name,
+                required: #required,
+                default: #default,
+                doc: #doc,
+            }
+        }
+    });
+
+    quote! { &[#(#infos),*] }
+}
+
+/*
+Print types and expressions closer to the way a person would write them than TokenStream does:
+
+    Vec<&'a str>              instead of  Vec < & 'a str >
+    [1, 2].len() as u8 & 3    instead of  [1 , 2] . len () as u8 & 3
+
+Every `<` and `>` in a type belongs to generics, so those are pulled together. In an expression
+they may be comparisons or shifts, so operators stay spaced out there.
+*/
+pub fn pretty(tokens: &impl ToTokens) -> String {
+    let out = pretty_expr(tokens);
+    replace_all(
+        out,
+        &[
+            (" < ", "<"),
+            ("< ", "<"),
+            (" <", "<"),
+            (" >", ">"),
+            ("& ", "&"),
+        ],
+    )
+}
+
+/// Like `pretty`, for expressions and attributes.
+pub fn pretty_expr(tokens: &impl ToTokens) -> String {
+    let out = replace_all(
+        tokens.to_token_stream().to_string(),
+        &[
+            (" :: ", "::"),
+            (":: ", "::"),
+            (" ,", ","),
+            (" ;", ";"),
+            (" . ", "."),
+            ("! (", "!("),
+            ("! [", "!["),
+            ("::core::default::Default::default", "Default::default"),
+        ],
+    );
+
+    // calls, but not `a + (b)`
+    let mut parts = out.split(" (");
+    let mut glued = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let call = glued.ends_with(|c: char| c.is_alphanumeric() || matches!(c, '_' | ')'));
+        glued += if call { "(" } else { " (" };
+        glued += part;
+    }
+    glued
+}
+
+fn replace_all(mut out: String, replacements: &[(&str, &str)]) -> String {
+    for (from, to) in replacements {
+        out = out.replace(from, to);
+    }
+    out
+}
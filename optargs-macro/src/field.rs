@@ -0,0 +1,465 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{
+    Attribute, Error, Expr, GenericArgument, Ident, Lit, LitStr, Meta, MetaNameValue,
+    PathArguments, Result, Token, Type,
+};
+
+use crate::rules::Rules;
+
+/// A single argument of an optfn or a field of an OptStruct.
+pub struct BuilderField {
+    // the name of the original parameter or field, or the key of a destructured parameter
+    pub ident: Ident,
+
+    // the name used at the call site, usually the same as ident
+    pub key: Ident,
+
+    // the type passed at the call site - `T` for `Option<T>` arguments
+    pub ty: Box<Type>,
+
+    pub attrs: ArgAttrs,
+}
+
+impl BuilderField {
+    pub fn new(ident: Ident, ty: Box<Type>, attrs: ArgAttrs) -> Self {
+        let key = attrs.name.clone().unwrap_or_else(|| ident.clone());
+        Self {
+            ident,
+            key,
+            ty,
+            attrs,
+        }
+    }
+
+    /*
+    The arms of the generated macro that store this argument in slot `id` of `inners`:
+
+        plot!(title: t)         (@setter_helper inners title title t)
+        plot!(title)            (@setter_helper inners title title)
+        plot!(title?: maybe)    (@setter_helper inners title ? maybe)
+
+    A required argument can't be passed as an Option, since it might be missing. Neither can one
+    that a rule needs to be passed, since the validator would count a None as passed.
+    */
+    pub fn setter_arms(&self, id: usize, required: bool, rules: &Rules) -> TokenStream2 {
+        let id = syn::Index::from(id);
+        let arg = &self.key;
+        let shorthand = self.assign(quote! { $src.#id }, quote! { $key });
+        let keyed = self.assign(quote! { $src.#id }, quote! { $value });
+        let maybe = match (required, rules.maybe_error(arg)) {
+            (true, _) => {
+                let msg = format!("`{0}` is required, so it can't be passed as `{0}?:`", arg);
+                quote! { ::core::compile_error!(#msg); }
+            }
+            (false, Some(msg)) => quote! { ::core::compile_error!(#msg); },
+            (false, None) => self.assign_option(quote! { $src.#id }, quote! { $value }),
+        };
+
+        quote! {
+            (@setter_helper $src:ident #arg $key:ident) => {
+                #shorthand
+            };
+            (@setter_helper $src:ident #arg $key:ident $value:expr) => {
+                #keyed
+            };
+            (@setter_helper $src:ident #arg ? $value:expr) => {
+                #maybe
+            };
+        }
+    }
+
+    /*
+    Store a value passed at the call site in its slot, an Option<T>:
+
+        inners.1 = Some(value);
+
+    `repeated` arguments collect every value into their Vec instead. `nullable` ones take either a
+    T or `null` and `None`, which are stored as Some(None).
+    */
+    pub fn assign(&self, slot: TokenStream2, value: TokenStream2) -> TokenStream2 {
+        match (self.attrs.repeated, self.attrs.nullable) {
+            (true, _) => quote! { #slot.get_or_insert_with(::std::vec::Vec::new).push(#value); },
+            (false, true) => quote! {{
+                #[allow(dead_code, non_upper_case_globals)]
+                const null: ::optargs::Null = ::optargs::Null;
+                #slot = ::core::option::Option::Some(
+                    ::optargs::__private::IntoNullable::into_nullable(#value),
+                );
+            }},
+            (false, false) => quote! { #slot = ::core::option::Option::Some(#value); },
+        }
+    }
+
+    // Like assign, for an Option<T> passed with `key?: value`, where None leaves the slot alone
+    pub fn assign_option(&self, slot: TokenStream2, value: TokenStream2) -> TokenStream2 {
+        match self.attrs.repeated {
+            true => {
+                let push = self.assign(slot, quote! { value });
+                quote! {
+                    if let ::core::option::Option::Some(value) = #value {
+                        #push
+                    }
+                }
+            }
+            false => quote! { #slot = #value; },
+        }
+    }
+
+    // `#[deprecated]` for the methods that stand in for this key, so passing it warns at the key
+    pub fn deprecation(&self) -> TokenStream2 {
+        match &self.attrs.deprecated {
+            Some(note) => quote! { #[deprecated(note = #note)] },
+            None => quote! {},
+        }
+    }
+
+    /*
+    Fill in an optional argument that might have been left out at the call site:
+
+        inners.1
+            .or_else(|| ::optargs::env::var::<u32>("VAR"))
+            .unwrap_or_else(|| 10)
+
+    `value` is an Option<T>, and we produce either an Option<T> or, with a default, a T.
+    */
+    pub fn resolve_optional(&self, value: TokenStream2) -> TokenStream2 {
+        let BuilderField { ty, attrs, .. } = self;
+
+        let value = match &attrs.env {
+            Some(var) => quote! { #value.or_else(|| ::optargs::env::var::<#ty>(#var)) },
+            None => value,
+        };
+
+        match &attrs.default {
+            Some(default) => quote! { #value.unwrap_or_else(|| #default) },
+            None => value,
+        }
+    }
+}
+
+/// Everything that can be placed in an `#[optarg(...)]` attribute.
+///
+/// ```ignore
+/// #[optarg(default = 10, name = "count")]
+/// ```
+#[derive(Default)]
+pub struct ArgAttrs {
+    // `default` uses Default::default(), `default = expr` uses the expression
+    pub default: Option<Expr>,
+
+    // `name = "key"` renames the key used at the call site
+    pub name: Option<Ident>,
+
+    // `env = "VAR"` reads the value from the environment when the key is left out
+    pub env: Option<LitStr>,
+
+    // `rest` collects any trailing positional arguments, like *args in python
+    pub rest: bool,
+
+    // `extra` collects every key that isn't a parameter, like **kwargs in python
+    pub extra: bool,
+
+    // `repeated` lets a Vec<T> be passed one item at a time, ie `series: a, series: b`
+    pub repeated: bool,
+
+    // `nullable` lets an Option<Option<T>> tell a left out key apart from `key: null`
+    pub nullable: bool,
+
+    // `deprecated = "note"` warns wherever the key is passed
+    pub deprecated: Option<LitStr>,
+
+    // `group = "source"` with `exclusive` lets at most one argument of the group be passed, and
+    // with `required` exactly one
+    pub group: Option<LitStr>,
+    pub group_required: bool,
+
+    // `requires = "y"` and `conflicts_with = "bar"`, both of which can be repeated
+    pub requires: Vec<LitStr>,
+    pub conflicts_with: Vec<LitStr>,
+
+    // not an optarg, but the doc comment is picked up here too
+    pub doc: String,
+
+    // the `#[cfg(...)]` attributes, which stay on the original
+    pub cfg: Vec<Attribute>,
+}
+
+impl ArgAttrs {
+    pub fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
+        let mut out = ArgAttrs {
+            doc: doc_comment(attrs),
+            cfg: attrs
+                .iter()
+                .filter(|a| a.path.is_ident("cfg"))
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+
+        let mut group_kind = None;
+        for attr in attrs.iter().filter(|a| a.path.is_ident("optarg")) {
+            let items = attr.parse_args_with(Punctuated::<ArgAttr, Token![,]>::parse_terminated)?;
+            for item in items {
+                match item {
+                    ArgAttr::Default(expr) => out.default = Some(*expr),
+                    ArgAttr::Name(name) => out.name = Some(name),
+                    ArgAttr::Env(var) => out.env = Some(var),
+                    ArgAttr::Rest => out.rest = true,
+                    ArgAttr::Extra => out.extra = true,
+                    ArgAttr::Repeated => out.repeated = true,
+                    ArgAttr::Nullable => out.nullable = true,
+                    ArgAttr::Deprecated(note) => out.deprecated = Some(note),
+                    ArgAttr::Group(group) => out.group = Some(group),
+                    ArgAttr::Requires(key) => out.requires.push(key),
+                    ArgAttr::ConflictsWith(key) => out.conflicts_with.push(key),
+                    ArgAttr::GroupKind(kind) => {
+                        out.group_required |= kind == "required";
+                        group_kind = Some(kind);
+                    }
+                }
+            }
+        }
+
+        // a repeated argument that's never passed is just empty
+        if out.repeated && out.default.is_none() {
+            out.default = Some(syn::parse_quote! { ::core::default::Default::default() });
+        }
+
+        match (&out.group, group_kind) {
+            (None, Some(kind)) => Err(Error::new_spanned(
+                &kind,
+                format!(
+                    "`{}` needs a group, ie #[optarg(group = \"source\", {})]",
+                    kind, kind
+                ),
+            )),
+            _ => Ok(out),
+        }
+    }
+
+    // like from_attrs, but also removes the optarg attributes so the original item still compiles
+    // doc comments aren't allowed on parameters either, but we can use them in the macro's docs
+    pub fn take(attrs: &mut Vec<Attribute>) -> Result<Self> {
+        let out = Self::from_attrs(attrs)?;
+        attrs.retain(|a| !a.path.is_ident("optarg") && !a.path.is_ident("doc"));
+        Ok(out)
+    }
+
+    pub fn is_optional(&self) -> bool {
+        self.default.is_some()
+    }
+
+    // `repeated` only works on a Vec, which it fills one item at a time
+    pub fn check_repeated(&self, ty: &Type) -> Result<()> {
+        match self.repeated && vec_item(ty).is_none() {
+            true => Err(Error::new_spanned(
+                ty,
+                "repeated arguments must be a Vec<T>",
+            )),
+            false => Ok(()),
+        }
+    }
+
+    // `nullable` needs a second Option to hold the null, and env can't parse one
+    pub fn check_nullable(&self, ty: &Type) -> Result<()> {
+        if !self.nullable {
+            return Ok(());
+        }
+        if let Some(env) = &self.env {
+            return Err(Error::new_spanned(
+                env,
+                "env can't be used on nullable arguments",
+            ));
+        }
+        match option_item(ty).and_then(|ty| option_item(&ty)) {
+            Some(_) => Ok(()),
+            None => Err(Error::new_spanned(
+                ty,
+                "nullable arguments must be an Option<Option<T>>",
+            )),
+        }
+    }
+
+    // env values are parsed with FromStr, which can only make owned values
+    pub fn check_env(&self, ty: &Type) -> Result<()> {
+        let env = match &self.env {
+            Some(env) => env,
+            None => return Ok(()),
+        };
+        match option_item(ty).unwrap_or_else(|| ty.clone()) {
+            Type::Reference(_) => Err(Error::new_spanned(
+                env,
+                "env can't be used on borrowed arguments, use an owned type like String",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Some attributes only make sense for arguments that can be left out
+    pub fn check_required(&self) -> Result<()> {
+        if let Some(env) = &self.env {
+            return Err(Error::new_spanned(
+                env,
+                "env can only be used on optional arguments",
+            ));
+        }
+        if let Some(group) = &self.group {
+            return Err(Error::new_spanned(
+                group,
+                "groups can only contain optional arguments",
+            ));
+        }
+        match self.requires.iter().chain(&self.conflicts_with).next() {
+            Some(key) => Err(Error::new_spanned(
+                key,
+                "requires and conflicts_with can only be used on optional arguments",
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+enum ArgAttr {
+    Default(Box<Expr>),
+    Name(Ident),
+    Env(LitStr),
+    Rest,
+    Extra,
+    Repeated,
+    Nullable,
+    Deprecated(LitStr),
+    Group(LitStr),
+
+    // `exclusive` or `required`
+    GroupKind(Ident),
+
+    Requires(LitStr),
+    ConflictsWith(LitStr),
+}
+
+impl Parse for ArgAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let ident: Ident = input.parse()?;
+        match ident.to_string().as_str() {
+            "default" => match input.parse::<Option<Token![=]>>()? {
+                Some(_) => Ok(ArgAttr::Default(input.parse()?)),
+                None => Ok(ArgAttr::Default(Box::new(
+                    syn::parse_quote! { ::core::default::Default::default() },
+                ))),
+            },
+            "name" => {
+                input.parse::<Token![=]>()?;
+                Ok(ArgAttr::Name(input.parse::<LitStr>()?.parse()?))
+            }
+            "env" => {
+                input.parse::<Token![=]>()?;
+                Ok(ArgAttr::Env(input.parse()?))
+            }
+            "rest" => Ok(ArgAttr::Rest),
+            "extra" => Ok(ArgAttr::Extra),
+            "repeated" => Ok(ArgAttr::Repeated),
+            "nullable" => Ok(ArgAttr::Nullable),
+            "deprecated" => {
+                input.parse::<Token![=]>()?;
+                Ok(ArgAttr::Deprecated(input.parse()?))
+            }
+            "group" => {
+                input.parse::<Token![=]>()?;
+                Ok(ArgAttr::Group(input.parse()?))
+            }
+            "exclusive" | "required" => Ok(ArgAttr::GroupKind(ident)),
+            "requires" => {
+                input.parse::<Token![=]>()?;
+                Ok(ArgAttr::Requires(input.parse()?))
+            }
+            "conflicts_with" => {
+                input.parse::<Token![=]>()?;
+                Ok(ArgAttr::ConflictsWith(input.parse()?))
+            }
+            _ => Err(Error::new_spanned(ident, "unknown optarg attribute")),
+        }
+    }
+}
+
+/// The `T` of a `Vec<T>`.
+pub fn vec_item(ty: &Type) -> Option<Type> {
+    item_of(ty, "Vec")
+}
+
+/// The `T` of an `Option<T>`.
+pub fn option_item(ty: &Type) -> Option<Type> {
+    item_of(ty, "Option")
+}
+
+// The `T` of a `Wrapper<T>`, going by the last segment of the path
+fn item_of(ty: &Type, wrapper: &str) -> Option<Type> {
+    match ty {
+        Type::Path(p) if p.qself.is_none() => p
+            .path
+            .segments
+            .last()
+            .filter(|seg| seg.ident == wrapper)
+            .and_then(|seg| match &seg.arguments {
+                PathArguments::AngleBracketed(params) => params.args.first(),
+                _ => None,
+            })
+            .and_then(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty.clone()),
+                _ => None,
+            }),
+        _ => None,
+    }
+}
+
+/// Collect the `///` comments of an item into a single string.
+pub fn doc_comment(attrs: &[Attribute]) -> String {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|a| a.path.is_ident("doc"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(Meta::NameValue(MetaNameValue {
+                lit: Lit::Str(s), ..
+            })) => Some(s.value()),
+            _ => None,
+        })
+        .map(|line| {
+            line.strip_prefix(' ')
+                .unwrap_or(&line)
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+
+    lines.join("\n").trim().to_string()
+}
+
+/// An option on the function or struct itself, ie `argv` or `doc = false`.
+pub struct ItemOption {
+    pub name: Ident,
+    pub value: Option<Lit>,
+}
+
+impl ItemOption {
+    // `flag` and `flag = true` both turn a flag on
+    pub fn flag(&self) -> Result<bool> {
+        match &self.value {
+            None => Ok(true),
+            Some(Lit::Bool(b)) => Ok(b.value),
+            Some(other) => Err(Error::new_spanned(other, "expected true or false")),
+        }
+    }
+}
+
+impl Parse for ItemOption {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name = input.parse()?;
+        let value = match input.parse::<Option<Token![=]>>()? {
+            Some(_) => Some(input.parse()?),
+            None => None,
+        };
+        Ok(Self { name, value })
+    }
+}
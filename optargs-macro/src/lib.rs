@@ -1,21 +1,68 @@
 use proc_macro::TokenStream;
 use quote::ToTokens;
 
+mod args;
+mod argv;
+mod builder;
+mod call;
+mod de;
+mod doc;
+mod field;
 mod optfn;
 mod optstruct;
+mod parse;
+mod partial;
+mod registry;
+mod rules;
+mod schema;
+mod validator;
 
 #[proc_macro_attribute]
-pub fn optfn(_attr: TokenStream, s: TokenStream) -> TokenStream {
-    match syn::parse::<optfn::OptFn>(s) {
+pub fn optfn(attr: TokenStream, s: TokenStream) -> TokenStream {
+    let (attr_tokens, item) = (attr.clone().into(), s.clone().into());
+    let parsed = syn::parse::<optfn::FnAttrs>(attr)
+        .and_then(|attrs| syn::parse::<optfn::OptFn>(s).map(|s| (attrs, s)));
+
+    match parsed {
         Err(e) => e.to_compile_error().into(),
-        Ok(s) => s.to_token_stream().into(),
+        Ok((_, s)) if s.has_cfg() => s.cfg_probe(attr_tokens, item).into(),
+        Ok((attrs, mut s)) => {
+            s.attrs = attrs;
+            s.to_token_stream().into()
+        }
+    }
+}
+
+// The second half of optfn for functions with `#[cfg]` parameters, see `OptFn::cfg_probe`
+#[doc(hidden)]
+#[proc_macro_derive(__OptFnCfg, attributes(optfn_cfg))]
+pub fn optfn_cfg(input: TokenStream) -> TokenStream {
+    match syn::parse::<syn::DeriveInput>(input).and_then(optfn::OptFn::from_probe) {
+        Err(e) => e.to_compile_error().into(),
+        Ok(s) => s.generate().into(),
     }
 }
 
-#[proc_macro_derive(OptStruct, attributes(builder))]
+#[proc_macro_derive(OptStruct, attributes(builder, optarg, optstruct))]
 pub fn optstruct(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     match syn::parse::<optstruct::OptStruct>(input) {
         Err(e) => e.to_compile_error().into(),
         Ok(s) => s.to_token_stream().into(),
     }
 }
+
+#[proc_macro]
+pub fn call(input: TokenStream) -> TokenStream {
+    match syn::parse::Parser::parse(call::Call::parse_call, input) {
+        Err(e) => e.to_compile_error().into(),
+        Ok(s) => s.to_token_stream().into(),
+    }
+}
+
+#[proc_macro]
+pub fn make(input: TokenStream) -> TokenStream {
+    match syn::parse::Parser::parse(call::Call::parse_make, input) {
+        Err(e) => e.to_compile_error().into(),
+        Ok(s) => s.to_token_stream().into(),
+    }
+}
@@ -1,17 +1,82 @@
-use std::str::FromStr;
-
-use proc_macro2::TokenStream as TokenStream2;
-use quote::{quote, ToTokens, TokenStreamExt};
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote, ToTokens};
 use syn::parse::{Parse, ParseStream};
-use syn::{Error, FnArg, GenericArgument, Ident, ItemFn, Path, PathArguments, Result, Type};
+use syn::punctuated::Punctuated;
+use syn::visit::Visit;
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    DeriveInput, Error, FnArg, GenericArgument, Generics, Ident, ItemFn, Path, PathArguments,
+    Result, Token, Type,
+};
 
-type BuilderField = (Ident, Box<Type>);
+use crate::args::Named;
+use crate::builder::{Builder, Retype, Setter};
+use crate::doc::{arg_infos, pretty_expr, MacroDoc};
+use crate::field::{doc_comment, option_item, vec_item, ArgAttrs, BuilderField, ItemOption};
+use crate::rules::Rules;
+use crate::validator::GenericGenerator;
+use crate::{parse, registry};
 
 pub struct OptFn {
-    original: ItemFn,
-    required_args: Vec<BuilderField>,
-    optional_args: Vec<BuilderField>,
-    name: Ident,
+    pub original: ItemFn,
+    pub required_args: Vec<BuilderField>,
+    pub optional_args: Vec<BuilderField>,
+
+    // the `#[optarg(rest)]` parameter, with the type of a single item
+    pub rest: Option<BuilderField>,
+
+    // the `#[optarg(extra)]` parameter, which gets every unknown key
+    pub extra: Option<BuilderField>,
+
+    pub name: Ident,
+
+    pub attrs: FnAttrs,
+
+    // parameters removed by a `#[cfg(...)]`, which only get an error message
+    pub disabled: Vec<BuilderField>,
+
+    // groups, `requires` and `conflicts_with` between the optional arguments
+    pub rules: Rules,
+}
+
+/// Options passed to the attribute itself, ie `#[optfn(doc = false)]`.
+pub struct FnAttrs {
+    // `doc = false` hides the macro from the docs
+    pub doc: bool,
+
+    // `register` makes the function callable by name, see `crate::registry`
+    pub register: bool,
+
+    // `parse` generates `plot_from_str`, see `crate::parse`
+    pub parse: bool,
+}
+
+impl Default for FnAttrs {
+    fn default() -> Self {
+        Self {
+            doc: true,
+            register: false,
+            parse: false,
+        }
+    }
+}
+
+impl Parse for FnAttrs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut out = FnAttrs::default();
+
+        let items = Punctuated::<ItemOption, Token![,]>::parse_terminated(input)?;
+        for item in items {
+            match item.name.to_string().as_str() {
+                "doc" => out.doc = item.flag()?,
+                "register" => out.register = item.flag()?,
+                "parse" => out.parse = item.flag()?,
+                _ => return Err(Error::new_spanned(item.name, "unknown optfn attribute")),
+            }
+        }
+
+        Ok(out)
+    }
 }
 
 impl Parse for OptFn {
@@ -26,24 +91,39 @@ impl Parse for OptFn {
     */
 
     fn parse(input: ParseStream) -> Result<Self> {
-        let orig: ItemFn = input.parse()?;
+        let mut orig: ItemFn = input.parse()?;
 
         // start by parsing positionals
         // optionals must come after positionals
         let mut parsing_optionals = false;
         let (mut required_args, mut optional_args) = (Vec::new(), Vec::new());
+        let mut rest = None;
+        let mut extra = None;
 
-        for arg in orig.sig.inputs.clone() {
+        let num_inputs = orig.sig.inputs.len();
+        for (position, arg) in orig.sig.inputs.iter_mut().enumerate() {
             match arg {
                 FnArg::Typed(arg) => Ok(arg),
                 FnArg::Receiver(r) => Err(Error::new_spanned(r, "optfn cannot be used on methods")),
             }
-            .and_then(|f| match (&f).pat.as_ref() {
-                syn::Pat::Ident(iden) => Ok((iden.clone(), f)),
-                other => Err(Error::new_spanned(other, "optfn cannot struct fields")),
+            .and_then(|f| {
+                // optarg attributes aren't real attributes, so strip them from the original
+                let attrs = ArgAttrs::take(&mut f.attrs)?;
+                // destructured parameters have no name of their own, so they need a key
+                match (f.pat.as_ref(), &attrs.name) {
+                    (syn::Pat::Ident(iden), _) => Ok((iden.ident.clone(), f, attrs)),
+                    (_, Some(name)) => Ok((name.clone(), f, attrs)),
+                    (other, None) => Err(Error::new_spanned(
+                        other,
+                        "destructured parameters need a key, ie #[optarg(name = \"pos\")]",
+                    )),
+                }
             })
-            .map(|(name, pat)| {
-                let is_optional = match pat.ty.as_ref() {
+            .and_then(|(name, pat, attrs)| {
+                attrs.check_repeated(&pat.ty)?;
+                attrs.check_nullable(&pat.ty)?;
+                attrs.check_env(&pat.ty)?;
+                let is_option = match pat.ty.as_ref() {
                     Type::Path(p) => {
                         if let Some(arg) = p.path.segments.first() {
                             arg.ident.to_string() == "Option"
@@ -53,12 +133,36 @@ impl Parse for OptFn {
                     }
                     _ => false,
                 };
-                (name, pat, is_optional)
+                match is_option && attrs.default.is_some() {
+                    true => Err(Error::new_spanned(
+                        &name,
+                        "Option arguments already default to None",
+                    )),
+                    false => Ok((name, pat, attrs, is_option)),
+                }
             })
-            .and_then(|(name, pat, is_optional)| {
-                match (is_optional, parsing_optionals) {
+            .and_then(|(name, pat, attrs, is_option)| {
+                if attrs.rest {
+                    if position + 1 != num_inputs {
+                        return Err(Error::new_spanned(
+                            name,
+                            "rest arguments must be the last parameter",
+                        ));
+                    }
+                    rest = Some(BuilderField::new(name, rest_item_type(&pat.ty)?, attrs));
+                    return Ok(());
+                }
+
+                if attrs.extra {
+                    extra_type(&pat.ty)?;
+                    extra = Some((position, BuilderField::new(name, pat.ty.clone(), attrs)));
+                    return Ok(());
+                }
+
+                match (is_option || attrs.is_optional(), parsing_optionals) {
                     (false, false) => {
-                        required_args.push((name.ident, pat.ty));
+                        attrs.check_required()?;
+                        required_args.push(BuilderField::new(name, pat.ty.clone(), attrs));
                         Ok(())
                     }
                     (false, true) => Err(Error::new_spanned(
@@ -66,7 +170,14 @@ impl Parse for OptFn {
                         "Non-optional values must be placed before optionals",
                     )),
                     (true, _) => {
-                        optional_args.push((name.ident, extract_type_from_option(pat.ty)?));
+                        let ty = match is_option {
+                            true => extract_type_from_option(pat.ty.clone())?,
+                            false => pat.ty.clone(),
+                        };
+                        if is_option && attrs.env.is_none() {
+                            check_fallback(&ty)?;
+                        }
+                        optional_args.push(BuilderField::new(name, ty, attrs));
                         parsing_optionals = true;
                         Ok(())
                     }
@@ -74,40 +185,380 @@ impl Parse for OptFn {
             })?;
         }
 
+        // `extra` comes last, or right before the rest argument
+        let extra = match extra {
+            Some((position, field)) if position + 1 + rest.iter().count() != num_inputs => {
+                return Err(Error::new_spanned(
+                    &field.key,
+                    "extra arguments must be the last parameter, or come right before the rest argument",
+                ))
+            }
+            other => other.map(|(_, field)| field),
+        };
+
         Ok(Self {
             name: orig.sig.ident.clone(),
             original: orig,
+            rules: Rules::new(&optional_args)?,
             required_args,
             optional_args,
+            rest,
+            extra,
+            attrs: FnAttrs::default(),
+            disabled: Vec::new(),
         })
     }
 }
 
+impl OptFn {
+    /*
+    An attribute sees the function before any `#[cfg]` is evaluated, so a disabled parameter would
+    still end up in the keys, the validator and the call. When a parameter has a `#[cfg]`, we hand
+    the function over to a derive on a hidden struct with a field per parameter instead:
+
+        #[derive(::optargs::__OptFnCfg)]
+        #[optfn_cfg([doc = false] fn plot(x: Vec<i32>, #[cfg(feature = "y")] y: i32) {})]
+        struct __OptArgsCfg_plot { x: (), #[cfg(feature = "y")] y: () }
+
+    rustc strips the disabled fields before running the derive, which tells us which parameters
+    are left. The function itself is still emitted here, with its `#[cfg]`s intact.
+    */
+    pub fn has_cfg(&self) -> bool {
+        self.required_args
+            .iter()
+            .chain(self.optional_args.iter())
+            .chain(self.extra.iter())
+            .chain(self.rest.iter())
+            .any(|field| !field.attrs.cfg.is_empty())
+    }
+
+    pub fn cfg_probe(&self, attr: TokenStream2, item: TokenStream2) -> TokenStream2 {
+        let probe = format_ident!("__OptArgsCfg_{}", self.name);
+        let fields = self
+            .required_args
+            .iter()
+            .chain(self.optional_args.iter())
+            .chain(self.extra.iter())
+            .chain(self.rest.iter())
+            .map(|BuilderField { key, attrs, .. }| {
+                let cfg = &attrs.cfg;
+                quote! { #(#cfg)* #key: (), }
+            });
+        let original = &self.original;
+
+        quote! {
+            #original
+
+            #[derive(::optargs::__OptFnCfg)]
+            #[optfn_cfg([#attr] #item)]
+            #[doc(hidden)]
+            #[allow(dead_code, non_camel_case_types)]
+            struct #probe { #(#fields)* }
+        }
+    }
+
+    // The other half of cfg_probe, keeping only the parameters whose field survived
+    pub fn from_probe(input: DeriveInput) -> Result<Self> {
+        let attr = input
+            .attrs
+            .iter()
+            .find(|a| a.path.is_ident("optfn_cfg"))
+            .ok_or_else(|| Error::new_spanned(&input.ident, "missing #[optfn_cfg]"))?;
+
+        let mut opt = attr.parse_args_with(|input: ParseStream| {
+            let attrs;
+            syn::bracketed!(attrs in input);
+            let attrs: FnAttrs = attrs.parse()?;
+            let mut opt: OptFn = input.parse()?;
+            opt.attrs = attrs;
+            Ok(opt)
+        })?;
+
+        let enabled: Vec<Ident> = match &input.data {
+            syn::Data::Struct(data) => data.fields.iter().filter_map(|f| f.ident.clone()).collect(),
+            _ => Vec::new(),
+        };
+
+        let mut disabled = Vec::new();
+        let mut keep = |fields: Vec<BuilderField>| {
+            let (on, off): (Vec<_>, Vec<_>) =
+                fields.into_iter().partition(|f| enabled.contains(&f.key));
+            disabled.extend(off);
+            on
+        };
+        opt.required_args = keep(std::mem::take(&mut opt.required_args));
+        opt.optional_args = keep(std::mem::take(&mut opt.optional_args));
+        opt.rest = keep(opt.rest.take().into_iter().collect()).pop();
+        opt.extra = keep(opt.extra.take().into_iter().collect()).pop();
+        opt.disabled = disabled;
+        opt.rules = Rules::new(&opt.optional_args)?;
+
+        Ok(opt)
+    }
+
+    // `PlotBuilder`, started by `plot::builder()`, and by `optargs::call!` through
+    // `plot::Validator::__builder()`
+    fn builder(&self) -> TokenStream2 {
+        let OptFn {
+            original,
+            required_args,
+            optional_args,
+            rest,
+            name,
+            ..
+        } = self;
+
+        let vis = &original.vis;
+        let Named {
+            generics,
+            types,
+            rest: rest_ty,
+            output,
+            ..
+        } = crate::args::named(self);
+        let (_, ty_generics, where_clause) = generics.split_for_impl();
+        let args_name = crate::args::args_ident(name);
+
+        // every field of the storage, for the setters that have to move them into a new one
+        let storage_fields: Vec<&Ident> = required_args
+            .iter()
+            .chain(optional_args.iter())
+            .chain(self.extra.iter())
+            .chain(rest.iter())
+            .map(|BuilderField { ident, .. }| ident)
+            .collect();
+
+        let setters = required_args
+            .iter()
+            .map(|f| (true, f))
+            .chain(optional_args.iter().map(|f| (false, f)))
+            .zip(types)
+            .map(|((required, field), ty)| {
+                let BuilderField {
+                    ident, key, attrs, ..
+                } = field;
+                let mut ty = match (attrs.repeated, attrs.nullable) {
+                    (true, _) => vec_item(&ty).unwrap_or(ty),
+                    (false, true) => option_item(&ty).unwrap_or(ty),
+                    (false, false) => ty,
+                };
+
+                // like the macro, an omitted `Option<impl Trait>` falls back to `Box<dyn Trait>`,
+                // except for nullable ones, where the value doesn't tell which type it holds
+                let fallback = !required
+                    && !attrs.repeated
+                    && !attrs.nullable
+                    && attrs.default.is_none()
+                    && attrs.env.is_none();
+                let params = match fallback {
+                    true => impl_fallbacks(&mut ty, &generics),
+                    false => Vec::new(),
+                };
+                let retype = match params.is_empty() {
+                    true => None,
+                    false => {
+                        let moved = storage_fields
+                            .iter()
+                            .filter(|other| **other != ident)
+                            .map(|other| quote! { #other: inner.#other, });
+                        Some(Retype {
+                            params,
+                            storage: quote! { #args_name },
+                            moved: quote! {
+                                #(#moved)*
+                                __marker: ::core::marker::PhantomData,
+                            },
+                        })
+                    }
+                };
+
+                Setter {
+                    key: key.clone(),
+                    ty,
+                    field: quote! { #ident },
+                    repeated: attrs.repeated,
+                    nullable: attrs.nullable,
+                    deprecated: attrs.deprecated.clone(),
+                    retype,
+                }
+            });
+        let mut setters = setters.collect::<Vec<_>>();
+        let optional = setters.split_off(required_args.len());
+
+        // optional arguments in a rule get a marker after the required ones
+        let rules = &self.rules;
+        let (tracked, optional): (Vec<_>, Vec<_>) = optional
+            .into_iter()
+            .partition(|Setter { key, .. }| rules.is_tracked(key));
+        let num_tracked = tracked.len();
+        setters.extend(tracked);
+        let rule_paths: Vec<_> = rules
+            .idents("__Rule")
+            .into_iter()
+            .map(|ident| quote! { #name::#ident })
+            .collect();
+        let bound = rules.bound(&rule_paths);
+
+        let mut call_args = TokenStream2::new();
+        for (required, field) in required_args
+            .iter()
+            .map(|f| (true, f))
+            .chain(optional_args.iter().map(|f| (false, f)))
+        {
+            let ident = &field.ident;
+            call_args.extend(match required {
+                true => quote! { args.#ident.unwrap(), },
+                false => {
+                    let value = field.resolve_optional(quote! { args.#ident });
+                    quote! { #value, }
+                }
+            });
+        }
+
+        // `call!` only passes known keys, so the extra argument stays empty
+        if let Some(BuilderField { ident, .. }) = &self.extra {
+            call_args.extend(quote! { args.#ident.unwrap_or_default(), });
+        }
+
+        let extra = match (rest, rest_ty) {
+            (Some(BuilderField { ident, key, .. }), Some(ty)) => {
+                call_args.extend(quote! { args.#ident.unwrap_or_default() });
+                quote! {
+                    #vis fn __rest(mut self, rest: ::std::vec::Vec<#ty>) -> Self {
+                        self.0.#ident = ::core::option::Option::Some(rest);
+                        self
+                    }
+
+                    /// Pass the trailing positional arguments.
+                    #vis fn #key(self, rest: ::std::vec::Vec<#ty>) -> Self {
+                        self.__rest(rest)
+                    }
+                }
+            }
+            _ => quote! {},
+        };
+
+        let asyncness = &original.sig.asyncness;
+        let unsafety = &original.sig.unsafety;
+        let awaited = asyncness.map(|_| quote! { .await });
+
+        // `plot::builder()` finishes with `.call()`, unless a key already took the name
+        let finisher = |method: Ident| {
+            quote! {
+                #vis #asyncness #unsafety fn #method(self) #output #bound {
+                    let args = self.0;
+                    #name(#call_args) #awaited
+                }
+            }
+        };
+        let public_call = match required_args
+            .iter()
+            .chain(optional_args.iter())
+            .chain(rest.iter())
+            .any(|f| f.key == "call")
+        {
+            true => quote! {},
+            false => {
+                let call = finisher(format_ident!("call"));
+                quote! {
+                    /// Call the function with every argument passed so far.
+                    #call
+                }
+            }
+        };
+        let hidden_call = finisher(format_ident!("__call"));
+
+        let builder_doc = format!(
+            "The arguments of [`{0}`], passed one method call at a time. Start one with `{0}::builder()`.\n\nRequired arguments are tracked in the last type parameter, so `call` is only there once all of them were passed.",
+            name
+        );
+        let builder = Builder {
+            vis,
+            name: crate::args::camel_ident(name, "Builder"),
+            doc: quote! { #[doc = #builder_doc] },
+            generics: &generics,
+            storage: quote! { #args_name #ty_generics },
+            init: quote! { ::core::default::Default::default() },
+            required: setters,
+            tracked: num_tracked,
+            optional,
+            extra,
+            finish: quote! {
+                #[doc(hidden)]
+                #hidden_call
+                #public_call
+            },
+        };
+
+        let (start, start_generics) = builder.start();
+        let builder_name = &builder.name;
+        let rule_impls = rules.impls(&builder.generator(), &quote! { #builder_name }, &rule_paths);
+        let validator =
+            GenericGenerator::new(required_args.len() + num_tracked, &Generics::default()).start();
+        let (impl_generics, _, _) = start_generics.split_for_impl();
+        let builder = builder.generate();
+
+        quote! {
+            #builder
+            #rule_impls
+
+            impl #name::Validator #validator {
+                #[doc(hidden)]
+                #vis fn __builder #impl_generics () -> #start #where_clause {
+                    <#start>::__new()
+                }
+            }
+
+            impl #impl_generics #name::__BuilderStart<#start> for #name::Validator #validator #where_clause {
+                fn __new() -> #start {
+                    <#start>::__new()
+                }
+            }
+        }
+    }
+}
+
 impl ToTokens for OptFn {
     fn to_tokens(&self, tokens: &mut TokenStream2) {
+        self.original.to_tokens(tokens);
+        tokens.extend(self.generate());
+    }
+}
+
+impl OptFn {
+    // everything but the function itself
+    pub fn generate(&self) -> TokenStream2 {
         let OptFn {
             original,
             required_args,
             optional_args,
             name,
+            disabled,
             ..
         } = self;
 
         let helper_defs = required_args
             .iter()
-            .chain(optional_args.iter())
+            .map(|f| (true, f))
+            .chain(optional_args.iter().map(|f| (false, f)))
             .enumerate()
-            .map(|(id, (arg, _ty))| {
-                let id = syn::Index::from(id);
-                quote! {
-                    (@setter_helper $src:ident #arg $key:ident) => {
-                        $src.#id = ::core::option::Option::Some($key);
-                    };
-                    (@setter_helper $src:ident #arg $key:ident $value:expr) => {
-                        $src.#id = ::core::option::Option::Some($value);
-                    };
-                }
-            });
+            .map(|(id, (required, field))| field.setter_arms(id, required, &self.rules));
+
+        // Keys that were turned off by a `#[cfg]` get a proper error instead of "no rules expected"
+        let disabled_defs = disabled.iter().map(|BuilderField { key, attrs, .. }| {
+            let cfg = attrs
+                .cfg
+                .iter()
+                .map(|attr| format!("#[cfg{}]", pretty_expr(&attr.tokens)))
+                .collect::<Vec<_>>();
+            let msg = format!("`{}` is disabled by {}", key, cfg.join(" "));
+            quote! {
+                (@setter_helper $src:ident #key $($value:tt)*) => {
+                    ::core::compile_error!(#msg);
+                };
+            }
+        });
+        let unknown = format!("`{}!` has no argument `", name);
 
         let inners_body = required_args
             .iter()
@@ -119,48 +570,527 @@ impl ToTokens for OptFn {
             .map(|f| (true, f))
             .chain(optional_args.iter().map(|f| (false, f)))
             .enumerate()
-            .map(|(id, (required, (_, ty)))| {
+            .map(|(id, (required, field))| {
                 let id = syn::Index::from(id);
+                let cast = cast(&field.ty);
                 match required {
-                    true => quote! {inners.#id.unwrap() as #ty,},
-                    false => quote! { inners.#id, },
+                    true => quote! {inners.#id.unwrap() #cast,},
+                    false => {
+                        let value = field.resolve_optional(quote! { inners.#id });
+                        quote! { #value, }
+                    }
                 }
             });
 
-        let validator =
-            GenericGenerator::new(required_args.len()).generate(required_args, optional_args);
+        // Keys passed next to `..args` replace the spread's values, so a required argument can come
+        // from either, and is only known to be missing once the call runs
+        let spread_fields = required_args
+            .iter()
+            .chain(optional_args.iter())
+            .map(|BuilderField { ident, .. }| quote! { args.#ident, });
+        let spread_missing = required_args.iter().enumerate().map(|(id, field)| {
+            let id = syn::Index::from(id);
+            let message = format!("missing required argument `{}` in {}!", field.key, name);
+            quote! {
+                if inners.#id.is_none() {
+                    ::core::panic!("{}", #message);
+                }
+            }
+        });
 
-        let ty_expanse = required_args
+        // a spread only shows which optional arguments were passed at runtime, so its rules panic,
+        // as the docs of the macro point out
+        let spread_rules = self.rules.check(
+            optional_args,
+            |field| {
+                let id = optional_args.iter().position(|f| f.key == field.key);
+                let id = syn::Index::from(required_args.len() + id.unwrap());
+                quote! { inners.#id.is_some() }
+            },
+            |message| {
+                let message = format!("{} in {}!", message, name);
+                quote! { ::core::panic!("{}", #message); }
+            },
+        );
+
+        // The validator lives in a module that shares the function's name, so `use`ing the
+        // function brings it along and every call site can reach it as `plot::Validator`
+        let rules = &self.rules;
+        let marked: Vec<&BuilderField> = required_args
+            .iter()
+            .chain(rules.tracked_fields(optional_args))
+            .collect();
+        let unmarked = optional_args
+            .iter()
+            .chain(disabled)
+            .filter(|f| !rules.is_tracked(&f.key));
+        let no_generics = Generics::default();
+        let generator =
+            GenericGenerator::new(marked.len(), &no_generics).tracking(rules.tracked.len());
+        let rule_idents = rules.idents("__Rule");
+        let rule_paths: Vec<_> = rule_idents.iter().map(|i| quote! { #i }).collect();
+        let bound = rules.bound(&rule_paths);
+        let validator_ident = Ident::new("Validator", Span::call_site());
+        let validator = generator.generate(
+            &syn::parse_quote! { pub },
+            &validator_ident,
+            quote! { () },
+            quote! {
+                pub fn build(self) #bound {}
+            },
+            &marked,
+            unmarked,
+        );
+        /*
+        `plot::builder()` can't name `PlotBuilder`, which lives next to the function and may be in
+        a fn body this module can't see into. It's picked by the one impl of `__BuilderStart`
+        instead, generated out there, and being a parameter of the trait keeps that impl as private
+        as the builder.
+        */
+        let validator_start = generator.start();
+        let (validator_generics, validator_any) =
+            (generator.gen_all_generic(), generator.gen_any());
+        let rule_traits = rules.traits(&syn::parse_quote! { pub }, &rule_idents);
+        let rule_impls = rules.impls(&generator, &quote! { #validator_ident }, &rule_paths);
+        let vis = &original.vis;
+
+        let args_struct = crate::args::generate(self);
+        let builder = self.builder();
+
+        let mut ty_expanse = required_args
             .iter()
             .chain(optional_args.iter())
-            .map(|(_, ty)| quote! { ::core::option::Option<#ty>, });
+            .map(|BuilderField { ty, .. }| {
+                // `impl Trait` isn't allowed in a `let`, so leave those up to inference
+                let mut ty = (**ty).clone();
+                ReplaceImpl::Infer.visit_type_mut(&mut ty);
+                quote! { ::core::option::Option<#ty>, }
+            })
+            .collect::<Vec<_>>();
 
-        ToTokens::to_tokens(
-            &quote! {
-                #original
+        /*
+        Inference can't pick a type for an `Option<impl Trait>` that was left out, so we scan the
+        keys for it and fall back to `Box<dyn Trait>` when it's missing.
+        */
+        let impl_optionals = optional_args
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| {
+                field.attrs.default.is_none() && field.attrs.env.is_none() && has_impl(&field.ty)
+            })
+            .map(|(id, field)| (id + required_args.len(), field))
+            .collect::<Vec<_>>();
+        let fallbacks = impl_optionals
+            .iter()
+            .map(|(id, BuilderField { key, ty, .. })| {
+                let id = syn::Index::from(*id);
+                let mut ty = (**ty).clone();
+                ReplaceImpl::Boxed.visit_type_mut(&mut ty);
+                quote! { #name!(@fallback #key [$($key)*] inners.#id, #ty); }
+            });
+        let fallback_defs = match impl_optionals.is_empty() {
+            true => quote! {},
+            false => {
+                let found = impl_optionals.iter().map(|(_, BuilderField { key, .. })| {
+                    quote! {
+                        (@fallback #key [#key $($rest:ident)*] $($hint:tt)*) => {};
+                    }
+                });
+                quote! {
+                    #( #found )*
+                    (@fallback $key:ident [$other:ident $($rest:ident)*] $($hint:tt)*) => {
+                        #name!(@fallback $key [$($rest)*] $($hint)*)
+                    };
+                    (@fallback $key:ident [] $slot:expr, $ty:ty) => {
+                        let _: &::core::option::Option<$ty> = &$slot;
+                    };
+                }
+            }
+        };
 
-                #[doc(hidden)]
-                #[macro_export]
-                macro_rules! #name {
-                    ($($key:ident $(: $value:expr)? ), * $(,)?) => {
-                        {
-                            #[allow(unused_mut)]
-                            let mut inners: (#( #ty_expanse)*) = (#( #inners_body )*);
-                            { $( #name! (@setter_helper inners $key $key $($value)? ); )* }
-                            #validator
-
-                            #[allow(unused_mut)]
-                            let mut validator = Validator::builder();
-                            validator $(.$key())* .build();
-                            #name(#( #call_body )*)
+        // A repeated key passed next to `..args` replaces the spread's values instead of adding to
+        // them, so its slot is emptied before any of the explicit keys are stored
+        let repeated = optional_args
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| field.attrs.repeated)
+            .map(|(id, BuilderField { key, .. })| {
+                let id = syn::Index::from(required_args.len() + id);
+                quote! {
+                    (@clear_helper $src:ident #key) => {
+                        $src.#id = ::core::option::Option::None;
+                    };
+                }
+            })
+            .collect::<Vec<_>>();
+        let (clear_defs, spread_clear) = match repeated.is_empty() {
+            true => (quote! {}, quote! {}),
+            false => (
+                quote! {
+                    #( #repeated )*
+                    (@clear_helper $src:ident $other:ident) => {};
+                },
+                quote! { $( #name!(@clear_helper inners $key); )* },
+            ),
+        };
+
+        let (call_rest, spread_rest) = match &self.rest {
+            Some(BuilderField { ident, .. }) => (
+                quote! { ::std::vec![$($rest),*] },
+                quote! { args.#ident.unwrap_or_default() },
+            ),
+            None => (quote! {}, quote! {}),
+        };
+
+        let fallbacks = fallbacks.collect::<Vec<_>>();
+        let mut inners_body = inners_body.collect::<Vec<_>>();
+        let mut call_body = call_body.collect::<Vec<_>>();
+        let mut spread_fields = spread_fields.collect::<Vec<_>>();
+
+        /*
+        Keys that aren't a parameter are an error, unless there's an extra argument to collect them.
+        It gets the slot after the optional arguments, and the validator skips the unknown keys:
+
+            let validator = plot::Validator::builder();
+            let validator = plot!(@mark x x validator);     // validator.x()
+            let validator = plot!(@mark color color validator); // validator
+            validator.build();
+        */
+        let (unknown_defs, validate) = match &self.extra {
+            None => (
+                quote! {
+                    (@setter_helper $src:ident $other:ident $($value:tt)*) => {
+                        ::core::compile_error!(::core::concat!(#unknown, ::core::stringify!($other), "`"));
+                    };
+                },
+                quote! { #name::Validator::builder() $(.$key())* .build(); },
+            ),
+            Some(BuilderField { ident, ty, .. }) => {
+                let slot = syn::Index::from(inners_body.len());
+                let mut ty = (**ty).clone();
+                ReplaceImpl::Infer.visit_type_mut(&mut ty);
+                ty_expanse.push(quote! { ::core::option::Option<#ty>, });
+                inners_body.push(quote! { ::core::option::Option::None, });
+                call_body.push(quote! { inners.#slot.unwrap_or_default(), });
+                spread_fields.push(quote! { args.#ident, });
+
+                let push = |value: TokenStream2| {
+                    quote! {
+                        ::core::iter::Extend::extend(
+                            $src.#slot.get_or_insert_with(::core::default::Default::default),
+                            ::core::iter::once((::core::stringify!($other), #value)),
+                        );
+                    }
+                };
+                let (maybe, shorthand, keyed) = (
+                    push(quote! { value }),
+                    push(quote! { $key }),
+                    push(quote! { $value }),
+                );
+                let marks = required_args
+                    .iter()
+                    .chain(optional_args.iter())
+                    .chain(disabled.iter())
+                    .map(|BuilderField { key, .. }| {
+                        quote! {
+                            (@mark #key $key:ident $validator:expr) => { $validator.$key() };
+                        }
+                    });
+                (
+                    quote! {
+                        (@setter_helper $src:ident $other:ident ? $value:expr) => {
+                            if let ::core::option::Option::Some(value) = $value {
+                                #maybe
+                            }
+                        };
+                        (@setter_helper $src:ident $other:ident $key:ident) => {
+                            #shorthand
+                        };
+                        (@setter_helper $src:ident $other:ident $key:ident $value:expr) => {
+                            #keyed
+                        };
+                        #( #marks )*
+                        (@mark $other:ident $key:ident $validator:expr) => { $validator };
+                    },
+                    quote! {
+                        let validator = #name::Validator::builder();
+                        $( let validator = #name!(@mark $key $key validator); )*
+                        validator.build();
+                    },
+                )
+            }
+        };
+
+        let body = |setters: TokenStream2| {
+            quote! {
+                {
+                    #[allow(unused_mut, unused_variables)]
+                    let mut inners: (#( #ty_expanse)*) = (#( #inners_body )*);
+                    { #setters }
+                    #( #fallbacks )*
+                    #validate
+                    #name(#( #call_body )* #call_rest)
+                }
+            }
+        };
+        let fast_body =
+            body(quote! { $( #name! (@setter_helper inners $key $key $($value)? ); )* });
+        let munched_body = body(quote! { $( #name! (@setter_helper inners $key $how $value); )* });
+
+        /*
+        The first arm covers plain `key: value` calls. Anything else is munched one item at a time
+        into `(key how value)` entries, where `how` is either the key again or `?` for `key?: value`.
+
+        Trailing positional arguments make the call ambiguous: `log!(level, a, b)` could be three
+        keys or a key and two positionals. Bare idents are only keys if they match a parameter, and
+        once the first positional shows up, everything after it is positional too.
+        */
+        let main_arms = match &self.rest {
+            None => quote! {
+                ($($key:ident $(: $value:expr)? ), * $(,)?) => {
+                    #fast_body
+                };
+            },
+            Some(_) => quote! {},
+        };
+
+        let shorthand_arms = match &self.rest {
+            None => quote! {
+                (@munch [$($done:tt)*] [] $key:ident $(, $($tail:tt)*)?) => {
+                    #name!(@munch [$($done)* ($key $key $key)] [] $($($tail)*)?)
+                };
+            },
+            Some(_) => {
+                let keys = required_args
+                    .iter()
+                    .chain(optional_args.iter())
+                    .chain(disabled.iter())
+                    .map(|BuilderField { key, .. }| {
+                        quote! {
+                            (@shorthand #key $key:ident [$($done:tt)*] [$($tail:tt)*]) => {
+                                #name!(@munch [$($done)* ($key $key $key)] [] $($tail)*)
+                            };
                         }
+                    });
+                quote! {
+                    (@munch [$($done:tt)*] [] $key:ident $(, $($tail:tt)*)?) => {
+                        #name!(@shorthand $key $key [$($done)*] [$($($tail)*)?])
+                    };
+                    (@munch [$($done:tt)*] [$($rest:tt)*] $value:expr $(, $($tail:tt)*)?) => {
+                        #name!(@munch [$($done)*] [$($rest)* $value,] $($($tail)*)?)
+                    };
+                    #( #keys )*
+                    (@shorthand $other:ident $value:ident [$($done:tt)*] [$($tail:tt)*]) => {
+                        #name!(@munch [$($done)*] [$value,] $($tail)*)
                     };
-                    #( #helper_defs )*
                 }
+            }
+        };
+
+        let munch_arms = quote! {
+            (@munch [$(($key:ident $how:tt $value:expr))*] [$($rest:expr,)*]) => {
+                #munched_body
+            };
+            (@munch [$($done:tt)*] [] $key:ident ? : $value:expr $(, $($tail:tt)*)?) => {
+                #name!(@munch [$($done)* ($key ? $value)] [] $($($tail)*)?)
+            };
+            (@munch [$($done:tt)*] [] $key:ident : $value:expr $(, $($tail:tt)*)?) => {
+                #name!(@munch [$($done)* ($key $key $value)] [] $($($tail)*)?)
+            };
+            // the spread arm only takes plain keys before a trailing `..args`
+            (@munch [$($done:tt)*] [$($rest:tt)*] .. $($tail:tt)*) => {
+                ::core::compile_error!("`..args` must come last, after `key: value` or `key` (`key?:` can't be used next to it)")
+            };
+            #shorthand_arms
+        };
 
+        // the spread's keys are only known at runtime, so its validator skips the required arguments
+        // and the rules
+        let spread_validate = match self.extra {
+            Some(_) => quote! {
+                let validator = #name::Validator::builder();
+                $( let validator = #name!(@mark $key $key validator); )*
+                validator.__spread();
             },
-            tokens,
-        );
+            None => quote! { #name::Validator::builder() $(.$key())* .__spread(); },
+        };
+
+        let docs = match self.attrs.doc {
+            true => MacroDoc {
+                summary: format!("Call `{}` with named and optional arguments.", name),
+                doc: &doc_comment(&original.attrs),
+                required_args,
+                optional_args,
+                rest: self.rest.as_ref(),
+                extra: self.extra.as_ref(),
+                spread_panics: !required_args.is_empty() || !self.rules.tracked.is_empty(),
+            }
+            .generate(),
+            false => quote! { #[doc(hidden)] },
+        };
+
+        let registration = match self.attrs.register {
+            true => registry::generate(self),
+            false => quote! {},
+        };
+
+        let arg_infos = arg_infos(required_args, optional_args, self.rest.as_ref());
+
+        let from_str = match self.attrs.parse {
+            true => parse::generate(self),
+            false => quote! {},
+        };
+
+        quote! {
+            #args_struct
+            #from_str
+
+            #[doc(hidden)]
+            #[allow(non_snake_case)]
+            #vis mod #name {
+                /// Every argument's key, type, default and docs.
+                pub const ARGS: &[::optargs::ArgInfo] = #arg_infos;
+
+                #[doc(hidden)]
+                pub trait __BuilderStart<B> {
+                    fn __new() -> B;
+                }
+
+                /// Pass the arguments one method call at a time, then run it with `.call()`.
+                #[allow(dead_code)]
+                pub fn builder<B>() -> B
+                where
+                    Validator #validator_start: __BuilderStart<B>,
+                {
+                    <Validator #validator_start as __BuilderStart<B>>::__new()
+                }
+
+                #validator
+                #rule_traits
+                // the required arguments of a spread are checked once the call runs
+                #[allow(dead_code)]
+                impl #validator_generics Validator #validator_any {
+                    pub fn __spread(self) {}
+                }
+                #rule_impls
+                #registration
+            }
+
+            #builder
+
+            #docs
+            #[macro_export]
+            macro_rules! #name {
+                #main_arms
+                ($($key:ident $(: $value:expr)? ,)* .. $args:expr $(,)?) => {
+                    {
+                        let args = $args;
+                        #[allow(unused_mut)]
+                        let mut inners: (#( #ty_expanse)*) = (#( #spread_fields )*);
+                        #spread_clear
+                        { $( #name! (@setter_helper inners $key $key $($value)? ); )* }
+                        #spread_validate
+                        #( #spread_missing )*
+                        #spread_rules
+                        #name(#( #call_body )* #spread_rest)
+                    }
+                };
+                #( #helper_defs )*
+                #clear_defs
+                #( #disabled_defs )*
+                #unknown_defs
+                #fallback_defs
+                #munch_arms
+                (@munch $($tokens:tt)*) => {
+                    ::core::compile_error!("expected `key: value`, `key?: value` or `..args`")
+                };
+                ($($tokens:tt)*) => {
+                    #name!(@munch [] [] $($tokens)*)
+                };
+            }
+        }
+    }
+}
+
+/*
+Rename the `impl Trait` parameters of `crate::args::named` in `ty` to fresh ones for a retyping
+setter, see `crate::builder::Retype`, along with the `Box<dyn Trait>` they start out as.
+*/
+fn impl_fallbacks(ty: &mut Type, generics: &Generics) -> Vec<(Ident, Ident, Type)> {
+    struct Rename<'a>(&'a Generics, Vec<(Ident, Ident, Type)>);
+
+    impl VisitMut for Rename<'_> {
+        fn visit_type_path_mut(&mut self, path: &mut syn::TypePath) {
+            let param = match (&path.qself, path.path.get_ident()) {
+                (None, Some(ident)) if ident.to_string().starts_with("__Impl") => {
+                    self.0.type_params().find(|p| p.ident == *ident)
+                }
+                _ => None,
+            };
+            let param = match param {
+                Some(param) => param,
+                None => return visit_mut::visit_type_path_mut(self, path),
+            };
+
+            let ident = &param.ident;
+            let fresh = format_ident!("{}", ident.to_string().replace("__Impl", "__Value"));
+            let bounds = &param.bounds;
+            self.1.push((
+                ident.clone(),
+                fresh.clone(),
+                syn::parse_quote! { ::std::boxed::Box<dyn #bounds> },
+            ));
+            *path = syn::parse_quote! { #fresh };
+        }
+    }
+
+    let mut rename = Rename(generics, Vec::new());
+    rename.visit_type_mut(ty);
+    rename.1
+}
+
+// `as T` helps integer literals along, but can't be written for `impl Trait`
+fn cast(ty: &Type) -> TokenStream2 {
+    match has_impl(ty) {
+        true => quote! {},
+        false => quote! { as #ty },
+    }
+}
+
+pub fn has_impl(ty: &Type) -> bool {
+    struct FindImpl(bool);
+    impl<'ast> Visit<'ast> for FindImpl {
+        fn visit_type_impl_trait(&mut self, _: &'ast syn::TypeImplTrait) {
+            self.0 = true;
+        }
+    }
+
+    let mut finder = FindImpl(false);
+    finder.visit_type(ty);
+    finder.0
+}
+
+// Rewrites the `impl Trait`s in a type
+enum ReplaceImpl {
+    // with `_`, for type annotations
+    Infer,
+
+    // with `Box<dyn Trait>`, for a concrete type that implements the trait
+    Boxed,
+}
+
+impl VisitMut for ReplaceImpl {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        match ty {
+            Type::ImplTrait(imp) => {
+                let bounds = &imp.bounds;
+                *ty = match self {
+                    ReplaceImpl::Infer => syn::parse_quote! { _ },
+                    ReplaceImpl::Boxed => syn::parse_quote! { ::std::boxed::Box<dyn #bounds> },
+                };
+            }
+            _ => visit_mut::visit_type_mut(self, ty),
+        }
     }
 }
 
@@ -195,145 +1125,105 @@ fn extract_type_from_option(ty: Box<Type>) -> Result<Box<Type>> {
     }
 }
 
-/*
-This struct lets us generate the correct const generics form depending on the arguments.
----
-So we can turn this function:
-
-    fn blah(a: u32, b: Option<u32>){}
-
-into
+// The traits that `Box<dyn Trait>` implements itself, besides auto traits
+const FALLBACK_TRAITS: &[&str] = &[
+    "Fn",
+    "FnMut",
+    "FnOnce",
+    "Display",
+    "Debug",
+    "Iterator",
+    "DoubleEndedIterator",
+    "ExactSizeIterator",
+    "Read",
+    "BufRead",
+    "Seek",
+];
+const AUTO_TRAITS: &[&str] = &["Send", "Sync", "Unpin"];
 
-    impl Builder<false> {
-                ^^^^^^^ -- this gets generated from a method
-        fn a(self) -> Builder<true> {
-                             ^^^^^^ -- this gets generated from a method
-            ...
-        }
-    }
---
-It's important to keep the original generics, and add any lifetimes for fields that start with &'_os.
-To do this, we always generate a borrowed lifetime and let the builder automatically add in the '_os lifetime
+/*
+An `Option<impl Trait>` that's left out falls back to `Box<dyn Trait>`, which only works if the box
+implements the trait too. That's up to the trait, so anything but the few in std that do is
+rejected here, instead of with an error about the box at every call that leaves it out.
 */
-struct GenericGenerator {
-    num_args: usize,
-}
-
-impl GenericGenerator {
-    fn new(num_args: usize) -> Self {
-        Self { num_args }
-    }
-
-    // generate the generics for an all-generic const
-    // used in the struct position
-    /*
-        pub struct ExampleBuilder<const M0: bool> {
-                                 ^^^^^^^^^^^^^^^^^^^^^^^^^^ this bit gets generated
-            a: Option<u32>,
-            b: Option<&'_o str>,
-        }
-    */
-    fn gen_all_generic(&self, exclude: usize) -> TokenStream2 {
-        let mut inner = quote! {};
-        for id in 0..self.num_args {
-            let idref = TokenStream2::from_str(format!("M{}", id).as_str()).unwrap();
-            if id != exclude {
-                inner.append_all(quote! { const #idref: bool, });
-            }
-        }
-        quote! { <#inner> }
-    }
-
-    // generate all the const generics with the same marker
-    /*
-    impl ExampleBuilder<true, M1> {
-                       ^^^^^^^^^^^^^^^^^^^^ generate this part
-        fn call(self) #ret {
-            #inner(#callerargs)
-        }
-    }
-    */
-    fn gen_all(&self, marker: bool) -> TokenStream2 {
-        let mut inner = quote! {};
-        for _ in 0..self.num_args {
-            inner.append_all(quote! { #marker, });
+fn check_fallback(ty: &Type) -> Result<()> {
+    struct FindImpls<'ast>(Vec<&'ast syn::TypeImplTrait>);
+    impl<'ast> Visit<'ast> for FindImpls<'ast> {
+        fn visit_type_impl_trait(&mut self, imp: &'ast syn::TypeImplTrait) {
+            self.0.push(imp);
         }
-        quote! { <#inner> }
     }
 
-    // generate all as generic, except for a single position with the marker
-    /*
-    impl<const A: bool> ExampleBuilder<false, A> {
-                                      ^^^^^^^^^^^^^^^ gen this
-        fn call(self, val: #ty) -> ExampleBuilder<true, A> {
-            ...
-        }
-    }
-    */
-    fn gen_positional(&self, position: usize, marker: bool) -> TokenStream2 {
-        //
-        let mut inner = quote! {};
-        for id in 0..self.num_args {
-            if id == position {
-                inner.append_all(quote! { #marker, });
-            } else {
-                let mtok = TokenStream2::from_str(format!("M{}", id).as_str()).unwrap();
-                inner.append_all(quote! { #mtok, });
+    let mut impls = FindImpls(Vec::new());
+    impls.visit_type(ty);
+    for imp in impls.0 {
+        let traits = imp.bounds.iter().filter_map(|bound| match bound {
+            syn::TypeParamBound::Trait(t) => t.path.segments.last().map(|seg| &seg.ident),
+            syn::TypeParamBound::Lifetime(_) => None,
+        });
+        let (mut found, mut unsupported) = (0, None);
+        for ident in traits.filter(|ident| !AUTO_TRAITS.iter().any(|t| ident == t)) {
+            found += 1;
+            if !FALLBACK_TRAITS.iter().any(|t| ident == t) {
+                unsupported = Some(ident);
             }
         }
-
-        quote! { <#inner> }
-    }
-
-    fn generate(
-        &self,
-        required_args: &Vec<BuilderField>,
-        optional_args: &Vec<BuilderField>,
-    ) -> TokenStream2 {
-        let impl_generics = self.gen_all_generic(usize::MAX);
-        let ty_gen = self.gen_all(false);
-        let builder_builder = quote! {
-            #[derive(Default)]
-            struct Validator #impl_generics;
-            impl Validator #ty_gen {
-                fn builder() -> Validator #ty_gen { Validator::default() }
-            }
+        let message = match (found, unsupported) {
+            (_, Some(ident)) => format!(
+                "an `Option<impl Trait>` that's left out falls back to `Box<dyn Trait>`, which doesn't implement `{}`; use a concrete type instead",
+                ident
+            ),
+            (2.., None) => "an `Option<impl Trait>` that's left out falls back to `Box<dyn Trait>`, which can't have more than one trait besides `Send`, `Sync` and `Unpin`".to_string(),
+            _ => continue,
         };
+        return Err(Error::new_spanned(imp, message));
+    }
+    Ok(())
+}
 
-        let mut builders = TokenStream2::new();
-        for (id, (name, _ty)) in required_args.iter().enumerate() {
-            let impl_generics = self.gen_all_generic(id);
-            let ty_gen_in = self.gen_positional(id, false);
-            let ty_gen_out = self.gen_positional(id, true);
-            builders.append_all(quote! {
-                impl #impl_generics Validator #ty_gen_in {
-                    fn #name(self) -> Validator #ty_gen_out { unsafe {::core::mem::transmute(self)} }
-                }
-            })
-        }
-
-        let impl_generics = self.gen_all_generic(usize::MAX);
-        let ty_gen = self.gen_positional(usize::MAX, false);
-        for (name, _ty) in optional_args {
-            builders.append_all(quote! {
-                impl #impl_generics Validator #ty_gen {
-                    #[allow(unused)]
-                    fn #name(self) -> Validator #ty_gen { self }
-                }
-            })
-        }
+// An extra argument is filled with `(key, value)` pairs, which a Vec or a HashMap can both collect
+fn extra_type(ty: &Type) -> Result<()> {
+    let collection = match ty {
+        Type::Path(p) if p.qself.is_none() => p.path.segments.last().map(|seg| &seg.ident),
+        _ => None,
+    };
+    match collection {
+        Some(ident) if ident == "Vec" || ident == "HashMap" => Ok(()),
+        _ => Err(Error::new_spanned(
+            ty,
+            "extra arguments must be a Vec<(&'static str, T)> or a HashMap<&'static str, T>",
+        )),
+    }
+}
 
-        let ty_gen = self.gen_all(true);
-        let caller = quote! {
-            impl Validator #ty_gen {
-                fn build(self) {}
-            }
-        };
+// The `T` of a `Vec<T>` or `impl IntoIterator<Item = T>`, the types a rest argument can have
+fn rest_item_type(ty: &Type) -> Result<Box<Type>> {
+    let item = match ty {
+        Type::Path(_) => vec_item(ty),
+        Type::ImplTrait(imp) => imp.bounds.iter().find_map(|bound| match bound {
+            syn::TypeParamBound::Trait(t) => t
+                .path
+                .segments
+                .last()
+                .filter(|seg| seg.ident == "IntoIterator")
+                .and_then(|seg| match &seg.arguments {
+                    PathArguments::AngleBracketed(params) => {
+                        params.args.iter().find_map(|arg| match arg {
+                            GenericArgument::Binding(b) if b.ident == "Item" => Some(b.ty.clone()),
+                            _ => None,
+                        })
+                    }
+                    _ => None,
+                }),
+            _ => None,
+        }),
+        _ => None,
+    };
 
-        quote! {
-            #builder_builder
-            #builders
-            #caller
-        }
-    }
+    item.map(Box::new).ok_or_else(|| {
+        Error::new_spanned(
+            ty,
+            "rest arguments must be a Vec<T> or impl IntoIterator<Item = T>",
+        )
+    })
 }
@@ -1,16 +1,82 @@
-use std::str::FromStr;
-
 use proc_macro2::TokenStream as TokenStream2;
-use quote::{quote, ToTokens, TokenStreamExt};
+use quote::{format_ident, quote, ToTokens};
 use syn::parse::{Parse, ParseStream};
-use syn::{DeriveInput, Error, GenericArgument, Ident, Path, PathArguments, Result, Type};
-
-type BuilderField = (Ident, Box<Type>);
+use syn::punctuated::Punctuated;
+use syn::{
+    DeriveInput, Error, GenericArgument, Generics, Ident, Path, PathArguments, Result, Token, Type,
+    Visibility,
+};
+
+use crate::builder::{Builder, Setter};
+use crate::doc::{arg_infos, MacroDoc};
+use crate::field::{doc_comment, option_item, vec_item, ArgAttrs, BuilderField, ItemOption};
+use crate::partial::{check_missing, check_rules};
+use crate::rules::Rules;
+use crate::validator::GenericGenerator;
 
 pub struct OptStruct {
-    name: Ident,
-    required_args: Vec<BuilderField>,
-    optional_args: Vec<BuilderField>,
+    pub name: Ident,
+    pub vis: Visibility,
+    pub generics: Generics,
+    pub required_args: Vec<BuilderField>,
+    pub optional_args: Vec<BuilderField>,
+    pub attrs: StructAttrs,
+    pub doc: String,
+
+    // groups, `requires` and `conflicts_with` between the optional fields
+    pub rules: Rules,
+}
+
+/// Extra items to generate, requested with `#[optstruct(...)]` on the struct.
+pub struct StructAttrs {
+    // `Struct::from_argv(std::env::args())`
+    pub argv: bool,
+
+    // `doc = false` hides the macro from the docs
+    pub doc: bool,
+
+    // `Struct::json_schema()`
+    pub json_schema: bool,
+
+    // `PartialStruct`, with `merge` and `finalize`
+    pub partial: bool,
+
+    // `impl Deserialize` for the struct and its partial, with the "serde" feature
+    pub deserialize: bool,
+
+    // `Struct::builder()`, which checks the required fields at runtime
+    pub builder: bool,
+}
+
+impl StructAttrs {
+    fn from_attrs(attrs: &[syn::Attribute]) -> Result<Self> {
+        let mut out = StructAttrs {
+            argv: false,
+            doc: true,
+            json_schema: false,
+            partial: false,
+            deserialize: false,
+            builder: false,
+        };
+
+        for attr in attrs.iter().filter(|a| a.path.is_ident("optstruct")) {
+            let items =
+                attr.parse_args_with(Punctuated::<ItemOption, Token![,]>::parse_terminated)?;
+            for item in items {
+                match item.name.to_string().as_str() {
+                    "argv" => out.argv = item.flag()?,
+                    "doc" => out.doc = item.flag()?,
+                    "json_schema" => out.json_schema = item.flag()?,
+                    "partial" => out.partial = item.flag()?,
+                    "deserialize" => out.deserialize = item.flag()?,
+                    "builder" => out.builder = item.flag()?,
+                    _ => return Err(Error::new_spanned(item.name, "unknown optstruct attribute")),
+                }
+            }
+        }
+
+        Ok(out)
+    }
 }
 
 impl Parse for OptStruct {
@@ -41,15 +107,28 @@ impl Parse for OptStruct {
         let (mut required_args, mut optional_args) = (Vec::new(), Vec::new());
 
         for field in &data.fields {
-            let syn::Field { ident, ty, .. } = field;
+            let syn::Field {
+                ident, ty, attrs, ..
+            } = field;
 
             let ident = ident.clone().ok_or(Error::new_spanned(
                 &name,
                 "Non-optional values must be placed before optionals",
             ))?;
 
-            // todo: allow attrs like #[default] to be placed on fields that don't have an obvious option
-            let is_optional = match ty {
+            let attrs = ArgAttrs::from_attrs(attrs)?;
+            attrs.check_repeated(ty)?;
+            attrs.check_nullable(ty)?;
+            attrs.check_env(ty)?;
+
+            if attrs.rest || attrs.extra {
+                return Err(Error::new_spanned(
+                    &ident,
+                    "rest and extra arguments are only supported by optfn",
+                ));
+            }
+
+            let is_option = match ty {
                 Type::Path(p) => {
                     if let Some(arg) = p.path.segments.first() {
                         arg.ident.to_string() == "Option"
@@ -60,12 +139,26 @@ impl Parse for OptStruct {
                 _ => false,
             };
 
-            match (is_optional, parsing_optionals) {
+            if is_option && attrs.default.is_some() {
+                return Err(Error::new_spanned(
+                    &ident,
+                    "Option fields already default to None",
+                ));
+            }
+
+            match (is_option || attrs.is_optional(), parsing_optionals) {
                 (true, _) => {
-                    optional_args.push((ident.clone(), extract_type_from_option(ty)?.clone()));
+                    let ty = match is_option {
+                        true => extract_type_from_option(ty)?,
+                        false => Box::new(ty.clone()),
+                    };
+                    optional_args.push(BuilderField::new(ident, ty, attrs));
                     parsing_optionals = true;
                 }
-                (false, false) => required_args.push((ident.clone(), Box::new(ty.clone()))),
+                (false, false) => {
+                    attrs.check_required()?;
+                    required_args.push(BuilderField::new(ident, Box::new(ty.clone()), attrs))
+                }
                 (false, true) => {
                     return Err(Error::new_spanned(
                         &name,
@@ -75,10 +168,35 @@ impl Parse for OptStruct {
             };
         }
 
+        let attrs = StructAttrs::from_attrs(&input.attrs)?;
+        if let Some(field) = optional_args
+            .iter()
+            .find(|f| attrs.argv && f.attrs.nullable)
+        {
+            return Err(Error::new_spanned(
+                &field.key,
+                "nullable fields can't be read from argv",
+            ));
+        }
+        if let Some(field) = optional_args
+            .iter()
+            .find(|f| attrs.argv && f.attrs.repeated)
+        {
+            return Err(Error::new_spanned(
+                &field.key,
+                "repeated fields can't be read from argv",
+            ));
+        }
+
         Ok(Self {
             name,
+            vis: input.vis.clone(),
+            generics: input.generics.clone(),
+            rules: Rules::new(&optional_args)?,
             optional_args,
             required_args,
+            attrs,
+            doc: doc_comment(&input.attrs),
         })
     }
 }
@@ -89,54 +207,272 @@ impl ToTokens for OptStruct {
             required_args,
             optional_args,
             name,
+            vis,
+            generics,
             ..
         } = self;
 
         let helper_defs = required_args
             .iter()
-            .chain(optional_args.iter())
+            .map(|f| (true, f))
+            .chain(optional_args.iter().map(|f| (false, f)))
             .enumerate()
-            .map(|(id, (arg, _ty))| {
-                let id = syn::Index::from(id);
-                quote! {
-                    (@setter_helper $src:ident #arg $key:ident) => {
-                        $src.#id = Some($key);
-                    };
-                    (@setter_helper $src:ident #arg $key:ident $value:expr) => {
-                        $src.#id = Some($value);
-                    };
-                }
-            });
+            .map(|(id, (required, field))| field.setter_arms(id, required, &self.rules));
+
+        // A derive only sees the fields left after `#[cfg]` is evaluated, so the positions below
+        // always line up. A disabled field is simply unknown, since there's no trace left of it
+        let unknown = format!("`{}` has no field `", name);
 
         let inners_body = required_args
             .iter()
             .chain(optional_args.iter())
-            .map(|_| quote! {None,});
+            .map(|_| quote! {None,})
+            .collect::<Vec<_>>();
 
         let call_body = required_args
             .iter()
             .map(|f| (true, f))
             .chain(optional_args.iter().map(|f| (false, f)))
             .enumerate()
-            .map(|(id, (required, (name, _ty)))| {
+            .map(|(id, (required, field))| {
                 let id = syn::Index::from(id);
+                let name = &field.ident;
                 match required {
                     true => quote! {
                         #name: inners.#id.unwrap(),
                     },
-                    false => quote! {
-                        #name: inners.#id,
+                    false => {
+                        let value = field.resolve_optional(quote! { inners.#id });
+                        quote! { #name: #value, }
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // The validator can't be reached by name from other modules, so the struct hands it out.
+        // `build` takes the finished struct, which lets the compiler infer its generics.
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        let validator_name = format_ident!("__OptArgsValidator{}", name);
+        let rules = &self.rules;
+        let marked: Vec<&BuilderField> = required_args
+            .iter()
+            .chain(rules.tracked_fields(optional_args))
+            .collect();
+        let unmarked = optional_args.iter().filter(|f| !rules.is_tracked(&f.key));
+        let generator = GenericGenerator::new(marked.len(), generics).tracking(rules.tracked.len());
+        let rule_idents = rules.idents(&format!("__OptArgsRule{}", name));
+        let rule_paths: Vec<_> = rule_idents.iter().map(|i| quote! { #i }).collect();
+        let bound = rules.bound(&rule_paths);
+        let validator = generator.generate(
+            vis,
+            &validator_name,
+            quote! { fn() -> #name #ty_generics },
+            quote! {
+                #vis fn build(self, built: #name #ty_generics) -> #name #ty_generics #bound {
+                    built
+                }
+            },
+            &marked,
+            unmarked,
+        );
+        let start = generator.start();
+        let rule_traits = rules.traits(vis, &rule_idents);
+        let rule_impls = rules.impls(&generator, &quote! { #validator_name }, &rule_paths);
+
+        // The builder behind `optargs::make!`, which carries the values along
+        let make_setters = || {
+            required_args
+                .iter()
+                .chain(optional_args.iter())
+                .enumerate()
+                .map(|(id, BuilderField { key, ty, attrs, .. })| {
+                    let id = syn::Index::from(id);
+                    Setter {
+                        key: key.clone(),
+                        ty: match (attrs.repeated, attrs.nullable) {
+                            (true, _) => vec_item(ty).unwrap_or_else(|| (**ty).clone()),
+                            (false, true) => option_item(ty).unwrap_or_else(|| (**ty).clone()),
+                            (false, false) => (**ty).clone(),
+                        },
+                        field: quote! { #id },
+                        repeated: attrs.repeated,
+                        nullable: attrs.nullable,
+                        deprecated: attrs.deprecated.clone(),
+                        retype: None,
+                    }
+                })
+        };
+        let mut setters = make_setters().collect::<Vec<_>>();
+        let optional = setters.split_off(required_args.len());
+        let storage = required_args
+            .iter()
+            .chain(optional_args.iter())
+            .map(|BuilderField { ty, .. }| quote! { ::core::option::Option<#ty>, });
+        let storage = quote! { (#(#storage)*) };
+        let runtime_storage = storage.clone();
+        let (tracked, optional): (Vec<_>, Vec<_>) = optional
+            .into_iter()
+            .partition(|Setter { key, .. }| rules.is_tracked(key));
+        let num_tracked = tracked.len();
+        setters.extend(tracked);
+        let builder = Builder {
+            vis,
+            name: format_ident!("__OptArgsBuilder{}", name),
+            doc: quote! { #[doc(hidden)] },
+            generics,
+            storage,
+            init: quote! { (#( #inners_body )*) },
+            required: setters,
+            tracked: num_tracked,
+            optional,
+            extra: quote! {},
+            finish: quote! {
+                #vis fn __build(self) -> #name #ty_generics #bound {
+                    let inners = self.0;
+                    #name {
+                        #( #call_body )*
+                    }
+                }
+            },
+        };
+        let (builder_start, _) = builder.start();
+        let builder_name = &builder.name;
+        let builder_rules =
+            rules.impls(&builder.generator(), &quote! { #builder_name }, &rule_paths);
+        let builder = builder.generate();
+
+        // `#[optstruct(builder)]` checks the required fields when it's built instead, so every
+        // setter goes without a marker
+        let runtime_builder = match self.attrs.builder {
+            true => {
+                let builder_name = format_ident!("{}Builder", name);
+                let doc = format!(
+                    "Builds a [`{0}`] one field at a time, checking the required fields in `build`.\n\nStart one with `{0}::builder()`.",
+                    name
+                );
+                let missing = check_missing(name, required_args, |id, _| {
+                    let id = syn::Index::from(id);
+                    quote! { self.0.#id }
+                });
+                let rules = check_rules(self, |field| {
+                    let id = optional_args.iter().position(|f| f.key == field.key);
+                    let id = syn::Index::from(required_args.len() + id.unwrap());
+                    quote! { self.0.#id.is_some() }
+                });
+                let runtime = Builder {
+                    vis,
+                    name: builder_name.clone(),
+                    doc: quote! { #[doc = #doc] },
+                    generics,
+                    storage: runtime_storage,
+                    init: quote! { (#( #inners_body )*) },
+                    required: Vec::new(),
+                    tracked: 0,
+                    optional: make_setters().collect(),
+                    extra: quote! {},
+                    finish: quote! {
+                        /// Check the groups and dependencies between the fields that are set,
+                        /// which `build` does after the required fields.
+                        #vis fn check(&self) -> ::core::result::Result<(), ::optargs::BrokenRule> {
+                            #rules
+                            ::core::result::Result::Ok(())
+                        }
+
+                        /// Build the struct, or list every required field that wasn't set, or return
+                        /// the first broken group or dependency.
+                        #vis fn build(self) -> ::core::result::Result<#name #ty_generics, ::optargs::BuildError> {
+                            #missing
+                            self.check()?;
+                            let inners = self.0;
+                            ::core::result::Result::Ok(#name {
+                                #( #call_body )*
+                            })
+                        }
                     },
+                };
+                let (runtime_start, _) = runtime.start();
+                let runtime = runtime.generate();
+                quote! {
+                    #runtime
+
+                    impl #impl_generics #name #ty_generics #where_clause {
+                        /// Start building this struct one field at a time, see `#[optstruct(builder)]`.
+                        #vis fn builder() -> #runtime_start {
+                            <#runtime_start>::__new()
+                        }
+                    }
                 }
-            });
+            }
+            false => quote! {},
+        };
 
-        let validator =
-            GenericGenerator::new(required_args.len()).generate(required_args, optional_args);
+        let deserialize = match self.attrs.deserialize {
+            true => crate::de::generate(self),
+            false => quote! {},
+        };
+
+        let argv = match self.attrs.argv {
+            true => crate::argv::generate(self),
+            false => quote! {},
+        };
+
+        let json_schema = match self.attrs.json_schema {
+            true => crate::schema::generate(self),
+            false => quote! {},
+        };
+
+        let partial = match self.attrs.partial {
+            true => crate::partial::generate(self),
+            false => quote! {},
+        };
+
+        let docs = match self.attrs.doc {
+            true => MacroDoc {
+                summary: format!("Build a `{}` with named and optional fields.", name),
+                doc: &self.doc,
+                required_args,
+                optional_args,
+                rest: None,
+                extra: None,
+                spread_panics: false,
+            }
+            .generate(),
+            false => quote! { #[doc(hidden)] },
+        };
+
+        let arg_infos = arg_infos(required_args, optional_args, None);
 
         ToTokens::to_tokens(
             &quote! {
+                #deserialize
+                #argv
+                #json_schema
+                #partial
+
+                #validator
+                #rule_traits
+                #rule_impls
+
+                impl #impl_generics #name #ty_generics #where_clause {
+                    /// Every field's key, type, default and docs.
+                    #vis const ARGS: &'static [::optargs::ArgInfo] = #arg_infos;
+
+                    #[doc(hidden)]
+                    #vis fn __optargs_validator() -> #validator_name #start {
+                        #validator_name::builder()
+                    }
 
-                #[doc(hidden)]
+                    #[doc(hidden)]
+                    #vis fn __optargs_builder() -> #builder_start {
+                        <#builder_start>::__new()
+                    }
+                }
+
+                #builder
+                #builder_rules
+                #runtime_builder
+
+                #docs
                 #[macro_export]
                 macro_rules! #name {
                     ($($key:ident $(: $value:expr)? ), * $(,)?) => {
@@ -144,18 +480,40 @@ impl ToTokens for OptStruct {
                             #[allow(unused_mut)]
                             let mut inners = (#( #inners_body )*);
                             { $( #name! (@setter_helper inners $key $key $($value)? ); )* }
-                            #validator
-
+                            #name::__optargs_validator() $(.$key())* .build(#name {
+                                #( #call_body )*
+                            })
+                        }
+                    };
+                    (@munch [$(($key:ident $how:tt $value:expr))*]) => {
+                        {
                             #[allow(unused_mut)]
-                            let mut validator = Validator::builder();
-                            validator $(.$key())* .build();
-
-                            #name{
+                            let mut inners = (#( #inners_body )*);
+                            { $( #name! (@setter_helper inners $key $how $value); )* }
+                            #name::__optargs_validator() $(.$key())* .build(#name {
                                 #( #call_body )*
-                            }
+                            })
                         }
                     };
+                    (@munch [$($done:tt)*] $key:ident ? : $value:expr $(, $($tail:tt)*)?) => {
+                        #name!(@munch [$($done)* ($key ? $value)] $($($tail)*)?)
+                    };
+                    (@munch [$($done:tt)*] $key:ident : $value:expr $(, $($tail:tt)*)?) => {
+                        #name!(@munch [$($done)* ($key $key $value)] $($($tail)*)?)
+                    };
+                    (@munch [$($done:tt)*] $key:ident $(, $($tail:tt)*)?) => {
+                        #name!(@munch [$($done)* ($key $key $key)] $($($tail)*)?)
+                    };
                     #( #helper_defs )*
+                    (@setter_helper $src:ident $other:ident $($value:tt)*) => {
+                        ::core::compile_error!(::core::concat!(#unknown, ::core::stringify!($other), "`"));
+                    };
+                    (@munch $($tokens:tt)*) => {
+                        ::core::compile_error!("expected `key: value` or `key?: value`")
+                    };
+                    ($($tokens:tt)*) => {
+                        #name!(@munch [] $($tokens)*)
+                    };
                 }
 
             },
@@ -194,146 +552,3 @@ fn extract_type_from_option(ty: &Type) -> Result<Box<Type>> {
         _ => panic!("TODO: error handling"),
     }
 }
-
-/*
-This struct lets us generate the correct const generics form depending on the arguments.
----
-So we can turn this function:
-
-    fn blah(a: u32, b: Option<u32>){}
-
-into
-
-    impl Builder<false> {
-                ^^^^^^^ -- this gets generated from a method
-        fn a(self) -> Builder<true> {
-                             ^^^^^^ -- this gets generated from a method
-            ...
-        }
-    }
---
-It's important to keep the original generics, and add any lifetimes for fields that start with &'_os.
-To do this, we always generate a borrowed lifetime and let the builder automatically add in the '_os lifetime
-*/
-struct GenericGenerator {
-    num_args: usize,
-}
-
-impl GenericGenerator {
-    fn new(num_args: usize) -> Self {
-        Self { num_args }
-    }
-
-    // generate the generics for an all-generic const
-    // used in the struct position
-    /*
-        pub struct ExampleBuilder<const M0: bool> {
-                                 ^^^^^^^^^^^^^^^^^^^^^^^^^^ this bit gets generated
-            a: Option<u32>,
-            b: Option<&'_o str>,
-        }
-    */
-    fn gen_all_generic(&self, exclude: usize) -> TokenStream2 {
-        let mut inner = quote! {};
-        for id in 0..self.num_args {
-            let idref = TokenStream2::from_str(format!("M{}", id).as_str()).unwrap();
-            if id != exclude {
-                inner.append_all(quote! { const #idref: bool, });
-            }
-        }
-        quote! { <#inner> }
-    }
-
-    // generate all the const generics with the same marker
-    /*
-    impl ExampleBuilder<true, M1> {
-                       ^^^^^^^^^^^^^^^^^^^^ generate this part
-        fn call(self) #ret {
-            #inner(#callerargs)
-        }
-    }
-    */
-    fn gen_all(&self, marker: bool) -> TokenStream2 {
-        let mut inner = quote! {};
-        for _ in 0..self.num_args {
-            inner.append_all(quote! { #marker, });
-        }
-        quote! { <#inner> }
-    }
-
-    // generate all as generic, except for a single position with the marker
-    /*
-    impl<const A: bool> ExampleBuilder<false, A> {
-                                      ^^^^^^^^^^^^^^^ gen this
-        fn call(self, val: #ty) -> ExampleBuilder<true, A> {
-            ...
-        }
-    }
-    */
-    fn gen_positional(&self, position: usize, marker: bool) -> TokenStream2 {
-        //
-        let mut inner = quote! {};
-        for id in 0..self.num_args {
-            if id == position {
-                inner.append_all(quote! { #marker, });
-            } else {
-                let mtok = TokenStream2::from_str(format!("M{}", id).as_str()).unwrap();
-                inner.append_all(quote! { #mtok, });
-            }
-        }
-
-        quote! { <#inner> }
-    }
-
-    fn generate(
-        &self,
-        required_args: &Vec<BuilderField>,
-        optional_args: &Vec<BuilderField>,
-    ) -> TokenStream2 {
-        let impl_generics = self.gen_all_generic(usize::MAX);
-        let ty_gen = self.gen_all(false);
-        let builder_builder = quote! {
-            #[derive(Default)]
-            struct Validator #impl_generics;
-            impl Validator #ty_gen {
-                fn builder() -> Validator #ty_gen { Validator::default() }
-            }
-        };
-
-        let mut builders = TokenStream2::new();
-        for (id, (name, _ty)) in required_args.iter().enumerate() {
-            let impl_generics = self.gen_all_generic(id);
-            let ty_gen_in = self.gen_positional(id, false);
-            let ty_gen_out = self.gen_positional(id, true);
-            builders.append_all(quote! {
-                impl #impl_generics Validator #ty_gen_in {
-                    fn #name(self) -> Validator #ty_gen_out { unsafe {::core::mem::transmute(self)} }
-                }
-            })
-        }
-
-        let impl_generics = self.gen_all_generic(usize::MAX);
-        let ty_gen = self.gen_positional(usize::MAX, false);
-        for (name, _ty) in optional_args {
-            builders.append_all(quote! {
-                impl #impl_generics Validator #ty_gen {
-                    #[allow(unused)]
-                    fn #name(self) -> Validator #ty_gen { self }
-                }
-            })
-        }
-
-        let ty_gen = self.gen_all(true);
-        let caller = quote! {
-            impl Validator #ty_gen {
-                fn build(self) {}
-            }
-        };
-
-        quote! {
-            #builder_builder
-            #builders
-            #caller
-        }
-    }
-}
@@ -0,0 +1,182 @@
+//! `#[optfn(parse)]` generates `plot_from_str("x=[1, 2, 3], title='hi'")`.
+//!
+//! The string is split into raw values by `optargs::parse::parse`, and every value is converted by
+//! the type of its parameter. The conversions are picked here, since a trait can't tell a `Vec<T>`
+//! apart from a `T: FromStr`:
+//!
+//! ```text
+//! Vec<Vec<i32>>   |raw| list(raw, |raw| list(raw, value::<i32>))
+//! &str            string
+//! f32             value::<f32>
+//! ```
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{ReturnType, Type};
+
+use crate::field::{option_item, vec_item, BuilderField};
+use crate::optfn::OptFn;
+use crate::registry;
+
+pub fn generate(opt: &OptFn) -> TokenStream2 {
+    match registry::check(opt, "parse") {
+        Ok(()) => from_str(opt),
+        Err(e) => e.to_compile_error(),
+    }
+}
+
+fn from_str(opt: &OptFn) -> TokenStream2 {
+    let OptFn {
+        original,
+        required_args,
+        optional_args,
+        rest,
+        name,
+        ..
+    } = opt;
+
+    let trimmed = name.to_string().trim_start_matches("r#").to_string();
+    let ident = format_ident!("{}_from_str", trimmed, span = name.span());
+    let vis = &original.vis;
+
+    let fields = required_args
+        .iter()
+        .map(|f| (true, f))
+        .chain(optional_args.iter().map(|f| (false, f)));
+
+    let mut keys = TokenStream2::new();
+    let mut args = Vec::new();
+    for (id, (required, field)) in fields.enumerate() {
+        let BuilderField { key, ty, attrs, .. } = field;
+        let key = key.to_string().trim_start_matches("r#").to_string();
+        let repeated = attrs.repeated;
+        keys.extend(quote! { ::optargs::parse::Key { name: #key, repeated: #repeated }, });
+
+        let values = quote! { &KEYS[#id], values.next().unwrap_or_default() };
+        args.push(match (required, attrs.repeated, attrs.nullable) {
+            (true, ..) => {
+                let convert = convert(ty);
+                quote! { ::optargs::parse::required(#values, #convert)? }
+            }
+            (false, true, _) => {
+                let convert = convert(&vec_item(ty).unwrap_or_else(|| *ty.clone()));
+                field.resolve_optional(quote! { ::optargs::parse::repeated(#values, #convert)? })
+            }
+            (false, false, true) => {
+                let convert = convert(&option_item(ty).unwrap_or_else(|| *ty.clone()));
+                field.resolve_optional(quote! { ::optargs::parse::nullable(#values, #convert)? })
+            }
+            (false, false, false) => {
+                let convert = convert(ty);
+                field.resolve_optional(quote! { ::optargs::parse::optional(#values, #convert)? })
+            }
+        });
+    }
+
+    // the rest argument takes a list, under its own key
+    if let Some(BuilderField { key, ty, .. }) = rest {
+        let id = args.len();
+        let key = key.to_string().trim_start_matches("r#").to_string();
+        keys.extend(quote! { ::optargs::parse::Key { name: #key, repeated: false }, });
+
+        let convert = convert(ty);
+        args.push(quote! {
+            ::optargs::parse::optional(
+                &KEYS[#id],
+                values.next().unwrap_or_default(),
+                |raw| ::optargs::parse::list(raw, #convert),
+            )?
+            .unwrap_or_default()
+        });
+    }
+
+    // borrowed values come out of the input, which has to outlive every lifetime of the function
+    let generics = &original.sig.generics;
+    let lifetimes: Vec<_> = generics.lifetimes().collect();
+    let (params, input) = match lifetimes.is_empty() {
+        true => (quote! {}, quote! { &str }),
+        false => (
+            quote! { <'__input, #(#lifetimes),*> },
+            quote! { &'__input str },
+        ),
+    };
+    let mut predicates: Vec<TokenStream2> = lifetimes
+        .iter()
+        .map(|def| {
+            let lt = &def.lifetime;
+            quote! { '__input: #lt }
+        })
+        .collect();
+    if let Some(where_clause) = &generics.where_clause {
+        let original = where_clause.predicates.iter();
+        predicates.extend(original.map(|pred| quote! { #pred }));
+    }
+    let where_clause = match predicates.is_empty() {
+        true => quote! {},
+        false => quote! { where #(#predicates),* },
+    };
+
+    // `None` counts as leaving a key out, except for nullable ones
+    let rules = opt.rules.check(
+        optional_args,
+        |field| {
+            let id = optional_args.iter().position(|f| f.key == field.key);
+            let id = required_args.len() + id.unwrap();
+            match field.attrs.nullable {
+                true => quote! { !values[#id].is_empty() },
+                false => quote! { values[#id].iter().any(|value| *value != "None") },
+            }
+        },
+        |message| quote! { return ::core::result::Result::Err(::optargs::parse::ParseError::Rule(#message)); },
+    );
+
+    let output = match &original.sig.output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ty) => quote! { #ty },
+    };
+
+    let doc = format!(
+        "Call [`{0}`] with arguments parsed from a string like `key=value, other='text'`.\n\nSee `optargs::parse` for how values are read.",
+        name
+    );
+
+    quote! {
+        #[doc = #doc]
+        #[allow(dead_code)]
+        #vis fn #ident #params (input: #input) -> ::core::result::Result<#output, ::optargs::parse::ParseError>
+        #where_clause
+        {
+            const KEYS: &[::optargs::parse::Key] = &[ #keys ];
+
+            let values = ::optargs::parse::parse(KEYS, input)?;
+            #rules
+
+            #[allow(unused_mut, unused_variables)]
+            let mut values = values.into_iter();
+
+            ::core::result::Result::Ok(#name(#(#args),*))
+        }
+    }
+}
+
+// The function that converts a raw value to `ty`
+fn convert(ty: &Type) -> TokenStream2 {
+    if let Some(item) = vec_item(ty) {
+        let item = convert(&item);
+        return quote! { |raw| ::optargs::parse::list(raw, #item) };
+    }
+
+    match ty {
+        Type::Reference(r) if r.mutability.is_none() && is_str(&r.elem) => {
+            quote! { ::optargs::parse::string }
+        }
+        _ => quote! { ::optargs::parse::value::<#ty> },
+    }
+}
+
+fn is_str(ty: &Type) -> bool {
+    match ty {
+        Type::Path(p) => p.qself.is_none() && p.path.is_ident("str"),
+        _ => false,
+    }
+}
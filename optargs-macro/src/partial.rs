@@ -0,0 +1,166 @@
+//! `#[optstruct(partial)]` generates `PartialScatter`, every field of `Scatter` as an `Option`.
+//!
+//! Partials are meant to be layered, ie defaults, then a file, then the environment, then the
+//! command line, where each layer only sets some of the fields:
+//!
+//! ```text
+//! let scatter = defaults.merge(file).merge(cli).finalize()?;
+//! ```
+//!
+//! Only `finalize` checks the required fields, and it reports all of the missing ones at once.
+//! Defaults and `env` are applied there too, so they sit below every layer. Groups and
+//! dependencies are checked next, and `finalize` returns the first broken one instead. `check`
+//! tests just the rules, for a layer that doesn't have the required fields yet.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::Ident;
+
+use crate::field::BuilderField;
+use crate::optstruct::OptStruct;
+
+pub fn generate(opt: &OptStruct) -> TokenStream2 {
+    let OptStruct {
+        name,
+        vis,
+        generics,
+        required_args,
+        optional_args,
+        ..
+    } = opt;
+
+    let partial = format_ident!("Partial{}", name);
+
+    let deserialize = match opt.attrs.deserialize {
+        true => crate::de::generate_partial(opt, &partial),
+        false => quote! {},
+    };
+
+    let mut fields = TokenStream2::new();
+    let mut defaults = TokenStream2::new();
+    let mut merges = TokenStream2::new();
+    for BuilderField { ident, ty, .. } in required_args.iter().chain(optional_args.iter()) {
+        fields.extend(quote! { pub #ident: ::core::option::Option<#ty>, });
+        defaults.extend(quote! { #ident: ::core::option::Option::None, });
+        merges.extend(quote! { #ident: other.#ident.or(self.#ident), });
+    }
+
+    let missing = check_missing(name, required_args, |_, field| {
+        let ident = &field.ident;
+        quote! { self.#ident }
+    });
+    let rules = check_rules(opt, |field| {
+        let ident = &field.ident;
+        quote! { self.#ident.is_some() }
+    });
+
+    let mut finish = TokenStream2::new();
+    for BuilderField { ident, .. } in required_args {
+        finish.extend(quote! { #ident: self.#ident.unwrap(), });
+    }
+    for field in optional_args {
+        let ident = &field.ident;
+        let value = field.resolve_optional(quote! { self.#ident });
+        finish.extend(quote! { #ident: #value, });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let doc = format!(
+        "Every field of [`{0}`] as an `Option`, for building it up in layers.\n\nLayers are combined with `merge` and turned into a `{0}` with `finalize`.",
+        name
+    );
+
+    quote! {
+        #[doc = #doc]
+        #vis struct #partial #impl_generics #where_clause {
+            #fields
+        }
+
+        impl #impl_generics ::core::default::Default for #partial #ty_generics #where_clause {
+            fn default() -> Self {
+                Self { #defaults }
+            }
+        }
+
+        impl #impl_generics #partial #ty_generics #where_clause {
+            /// Layer `other` on top of this, so every field it sets wins.
+            #vis fn merge(self, other: Self) -> Self {
+                Self { #merges }
+            }
+
+            /// Check the groups and dependencies between the fields that are set, which `finalize`
+            /// does after the required fields.
+            #vis fn check(&self) -> ::core::result::Result<(), ::optargs::BrokenRule> {
+                #rules
+                ::core::result::Result::Ok(())
+            }
+
+            /// Build the struct, filling in defaults, or list every required field that's missing,
+            /// or return the first broken group or dependency.
+            #vis fn finalize(self) -> ::core::result::Result<#name #ty_generics, ::optargs::BuildError> {
+                #missing
+                self.check()?;
+                ::core::result::Result::Ok(#name { #finish })
+            }
+        }
+
+        #deserialize
+    }
+}
+
+/*
+Return every required field that's still None, for `finalize` and the `#[optstruct(builder)]`
+builder, converted into their error type. `slot` gives the Option holding the field at an index:
+
+    let mut missing = Vec::new();
+    if self.x.is_none() {
+        missing.push("x");
+    }
+    if !missing.is_empty() {
+        return Err(From::from(MissingFields { name: "Scatter", fields: missing }));
+    }
+*/
+pub fn check_missing(
+    name: &Ident,
+    required_args: &[BuilderField],
+    slot: impl Fn(usize, &BuilderField) -> TokenStream2,
+) -> TokenStream2 {
+    let name = name.to_string();
+    let checks = required_args.iter().enumerate().map(|(id, field)| {
+        let key = field.key.to_string().trim_start_matches("r#").to_string();
+        let slot = slot(id, field);
+        quote! {
+            if #slot.is_none() {
+                missing.push(#key);
+            }
+        }
+    });
+
+    quote! {
+        #[allow(unused_mut)]
+        let mut missing = ::std::vec::Vec::new();
+        #(#checks)*
+        if !missing.is_empty() {
+            return ::core::result::Result::Err(::core::convert::From::from(::optargs::MissingFields {
+                name: #name,
+                fields: missing,
+            }));
+        }
+    }
+}
+
+// Return the first rule between the optional fields that's broken, like `check_missing`
+pub fn check_rules(
+    opt: &OptStruct,
+    passed: impl Fn(&BuilderField) -> TokenStream2,
+) -> TokenStream2 {
+    let name = opt.name.to_string();
+    opt.rules.check(&opt.optional_args, passed, |message| {
+        quote! {
+            return ::core::result::Result::Err(::optargs::BrokenRule {
+                name: #name,
+                message: #message,
+            });
+        }
+    })
+}
@@ -0,0 +1,216 @@
+//! `#[optfn(register)]`, which makes a function callable by name through `optargs::Registry`.
+//!
+//! The function's module gets a wrapper that takes `(key, Box<dyn Any>)` pairs, downcasts them
+//! into the same slots the macro uses, and boxes the output:
+//!
+//! ```text
+//! pub const REGISTRATION: ::optargs::Registration = ...;
+//!
+//! fn call(args: Args) -> Result<Box<dyn Any>, ArgError> {
+//!     let mut slots: (Option<Vec<i32>>, Option<&'static str>) = (None, None);
+//!     for (key, value) in args {
+//!         match key {
+//!             "x" => slots.0 = Some(downcast("plot", "x", value)?),
+//!             "title" => slots.1 = Some(downcast("plot", "title", value)?),
+//!             key => return Err(ArgError::Unknown { .. }),
+//!         }
+//!     }
+//!     Ok(Box::new(plot(slots.0.ok_or(ArgError::Missing { .. })?, slots.1)))
+//! }
+//! ```
+//!
+//! `Any` needs `'static` types, so every lifetime in the parameters becomes `'static`.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::visit_mut::{self, VisitMut};
+use syn::{Error, GenericParam, Lifetime, Result, Type, TypeReference};
+
+use crate::field::BuilderField;
+use crate::optfn::{has_impl, OptFn};
+
+pub fn generate(opt: &OptFn) -> TokenStream2 {
+    match check(opt, "register") {
+        Ok(()) => wrapper(opt),
+        Err(e) => e.to_compile_error(),
+    }
+}
+
+// A wrapper can only call functions whose types are all known and safe to call, which is true for
+// `parse` as well
+pub fn check(opt: &OptFn, attr: &str) -> Result<()> {
+    let sig = &opt.original.sig;
+    let generic = sig
+        .generics
+        .params
+        .iter()
+        .find(|param| !matches!(param, GenericParam::Lifetime(_)));
+
+    if let Some(param) = generic {
+        return Err(Error::new_spanned(
+            param,
+            format!("{} can't be used on generic functions", attr),
+        ));
+    }
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(Error::new_spanned(
+            asyncness,
+            format!("{} can't be used on async functions", attr),
+        ));
+    }
+    if let Some(unsafety) = &sig.unsafety {
+        return Err(Error::new_spanned(
+            unsafety,
+            format!("{} can't be used on unsafe functions", attr),
+        ));
+    }
+    if let Some(extra) = &opt.extra {
+        return Err(Error::new_spanned(
+            &extra.key,
+            format!("{} can't be used with extra arguments", attr),
+        ));
+    }
+
+    let mut fields = opt
+        .required_args
+        .iter()
+        .chain(opt.optional_args.iter())
+        .chain(opt.rest.iter());
+    if let Some(field) = fields.find(|field| has_impl(&field.ty)) {
+        return Err(Error::new_spanned(
+            &field.ty,
+            format!("{} can't be used with `impl Trait` parameters", attr),
+        ));
+    }
+
+    Ok(())
+}
+
+fn wrapper(opt: &OptFn) -> TokenStream2 {
+    let OptFn {
+        required_args,
+        optional_args,
+        rest,
+        name,
+        ..
+    } = opt;
+
+    let function = name.to_string().trim_start_matches("r#").to_string();
+
+    let fields = required_args
+        .iter()
+        .map(|f| (true, f))
+        .chain(optional_args.iter().map(|f| (false, f)));
+
+    let mut types = Vec::new();
+    let mut arms = Vec::new();
+    let mut call_args = Vec::new();
+    for (id, (required, field)) in fields.enumerate() {
+        let slot = syn::Index::from(id);
+        let ty = statics(&field.ty);
+        let key = field.key.to_string().trim_start_matches("r#").to_string();
+
+        types.push(quote! { ::core::option::Option<#ty> });
+        arms.push(quote! {
+            #key => slots.#slot = ::core::option::Option::Some(
+                ::optargs::registry::downcast(#function, #key, value)?
+            ),
+        });
+        call_args.push(match required {
+            true => quote! {
+                slots.#slot.ok_or(::optargs::ArgError::Missing { function: #function, key: #key })?
+            },
+            false => field.resolve_optional(quote! { slots.#slot }),
+        });
+    }
+
+    // the rest argument is passed as a whole Vec, under its own key
+    if let Some(BuilderField { key, ty, .. }) = rest {
+        let slot = syn::Index::from(types.len());
+        let ty = statics(ty);
+        let key = key.to_string().trim_start_matches("r#").to_string();
+
+        types.push(quote! { ::core::option::Option<::std::vec::Vec<#ty>> });
+        arms.push(quote! {
+            #key => slots.#slot = ::core::option::Option::Some(
+                ::optargs::registry::downcast(#function, #key, value)?
+            ),
+        });
+        call_args.push(quote! { slots.#slot.unwrap_or_default() });
+    }
+
+    let nones = types
+        .iter()
+        .map(|_| quote! { ::core::option::Option::None });
+
+    let rules = opt.rules.check(
+        optional_args,
+        |field| {
+            let id = optional_args.iter().position(|f| f.key == field.key);
+            let slot = syn::Index::from(required_args.len() + id.unwrap());
+            quote! { slots.#slot.is_some() }
+        },
+        |message| {
+            quote! {
+                return ::core::result::Result::Err(::optargs::ArgError::Rule {
+                    function: #function,
+                    message: #message,
+                });
+            }
+        },
+    );
+
+    quote! {
+        pub const REGISTRATION: ::optargs::Registration = ::optargs::Registration {
+            name: #function,
+            call: __registry::call,
+        };
+
+        // a module of its own, so the parent's names don't leak into the validator's module
+        mod __registry {
+            #[allow(unused_imports)]
+            use super::super::*;
+
+            pub fn call(
+                args: ::optargs::registry::Args,
+            ) -> ::core::result::Result<::std::boxed::Box<dyn ::core::any::Any>, ::optargs::ArgError> {
+                #[allow(unused_mut)]
+                let mut slots: (#(#types,)*) = (#(#nones,)*);
+                for (key, value) in args {
+                    match key {
+                        #(#arms)*
+                        key => {
+                            return ::core::result::Result::Err(::optargs::ArgError::Unknown {
+                                function: #function,
+                                key: key.to_string(),
+                            })
+                        }
+                    }
+                }
+                #rules
+                ::core::result::Result::Ok(::std::boxed::Box::new(super::super::#name(#(#call_args),*)))
+            }
+        }
+    }
+}
+
+// `&str` and `&'a str` both become `&'static str`
+fn statics(ty: &Type) -> Type {
+    struct Statics;
+    impl VisitMut for Statics {
+        fn visit_type_reference_mut(&mut self, r: &mut TypeReference) {
+            if r.lifetime.is_none() {
+                r.lifetime = Some(syn::parse_quote! { 'static });
+            }
+            visit_mut::visit_type_reference_mut(self, r);
+        }
+
+        fn visit_lifetime_mut(&mut self, l: &mut Lifetime) {
+            *l = syn::parse_quote! { 'static };
+        }
+    }
+
+    let mut ty = ty.clone();
+    Statics.visit_type_mut(&mut ty);
+    ty
+}
@@ -0,0 +1,348 @@
+//! Rules between optional arguments, checked by the validator at compile time.
+//!
+//! `#[optarg(group = "source", exclusive)]` lets at most one argument of the group be passed, and
+//! `#[optarg(group = "source", required)]` exactly one. `#[optarg(requires = "y")]` needs `y` to
+//! be passed along, and `#[optarg(conflicts_with = "bar")]` forbids `bar`.
+//!
+//! Every optional argument that shows up in a rule gets a marker in the validator after the
+//! required ones, which is flipped when it's passed, just like a required argument. Each rule then
+//! becomes a trait implemented for the states that satisfy it, and `build` asks for all of them:
+//!
+//! ```text
+//! #[diagnostic::on_unimplemented(message = "only one of `path` or `url` can be passed")]
+//! pub trait __Rule0 {}
+//! impl __Rule0 for Validator<Marker<false, Marker<false, End>>> {}
+//! impl __Rule0 for Validator<Marker<true, Marker<false, End>>> {}
+//! impl __Rule0 for Validator<Marker<false, Marker<true, End>>> {}
+//!
+//! impl<const M0: bool, const M1: bool> Validator<Marker<M0, Marker<M1, End>>> {
+//!     pub fn build(self) where Self: __Rule0 {}
+//! }
+//! ```
+//!
+//! A broken rule is an error about `build`, which carries the rule's message on Rust 1.78 and
+//! later, see `build.rs`. It points at `#[optfn]` or the derive, since that's where `build` comes
+//! from, with the call it's about labelled below. Passing a tracked argument twice is caught by
+//! its marker instead, as an error about the method named after it, pointing at the second key.
+//!
+//! Anything that only knows which arguments were passed once it runs, like a spread, checks the
+//! same rules with `Rules::check` instead.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Error, Ident, LitStr, Result, Visibility};
+
+use crate::field::BuilderField;
+use crate::validator::GenericGenerator;
+
+pub struct Rules {
+    // the optional arguments that show up in any rule, in the order of their markers
+    pub tracked: Vec<Ident>,
+
+    rules: Vec<Rule>,
+
+    // arguments that can't be passed as `key?:`, since a None would count as passed, with the error
+    maybe_errors: Vec<(Ident, String)>,
+}
+
+struct Rule {
+    message: String,
+
+    // the states that satisfy the rule, as values for some of the tracked markers
+    allowed: Vec<Vec<(usize, bool)>>,
+}
+
+impl Rules {
+    pub fn new(optional_args: &[BuilderField]) -> Result<Self> {
+        let mut tracked: Vec<Ident> = Vec::new();
+        let mut track = |key: &Ident| match tracked.iter().position(|k| k == key) {
+            Some(id) => id,
+            None => {
+                tracked.push(key.clone());
+                tracked.len() - 1
+            }
+        };
+
+        // in the order the groups first show up
+        let mut groups: Vec<Group> = Vec::new();
+        for field in optional_args {
+            if let Some(group) = &field.attrs.group {
+                let member = (track(&field.key), &field.key);
+                match groups.iter_mut().find(|g| g.name == group.value()) {
+                    Some(group) => {
+                        group.members.push(member);
+                        group.required |= field.attrs.group_required;
+                    }
+                    None => groups.push(Group {
+                        name: group.value(),
+                        members: vec![member],
+                        required: field.attrs.group_required,
+                    }),
+                }
+            }
+        }
+
+        let mut rules = Vec::new();
+        let mut maybe_errors = Vec::new();
+        for Group {
+            name,
+            members,
+            required,
+        } in groups
+        {
+            let keys = list(members.iter().map(|(_, key)| *key));
+            if required {
+                maybe_errors.extend(members.iter().map(|(_, key)| {
+                    let msg = format!(
+                        "`{0}` is in the required group `{1}`, so it can't be passed as `{0}?:`",
+                        key, name
+                    );
+                    ((*key).clone(), msg)
+                }));
+            }
+            let message = match required {
+                true => format!("exactly one of {} must be passed (group `{}`)", keys, name),
+                false => format!("only one of {} can be passed (group `{}`)", keys, name),
+            };
+
+            // one of the members, or none of them if that's allowed
+            let one = |chosen: Option<usize>| -> Vec<(usize, bool)> {
+                members
+                    .iter()
+                    .map(|(id, _)| (*id, Some(*id) == chosen))
+                    .collect()
+            };
+            let mut allowed: Vec<_> = members.iter().map(|(id, _)| one(Some(*id))).collect();
+            if !required {
+                allowed.push(one(None));
+            }
+
+            rules.push(Rule { message, allowed });
+        }
+
+        // `requires` and `conflicts_with` only look at two arguments
+        for field in optional_args {
+            let pairs = field
+                .attrs
+                .requires
+                .iter()
+                .map(|other| (other, true))
+                .chain(
+                    field
+                        .attrs
+                        .conflicts_with
+                        .iter()
+                        .map(|other| (other, false)),
+                );
+
+            for (other, requires) in pairs {
+                let other_key = find(optional_args, other)?;
+                if other_key == &field.key {
+                    return Err(Error::new_spanned(
+                        other,
+                        "an argument can't refer to itself",
+                    ));
+                }
+
+                let (this, that) = (track(&field.key), track(other_key));
+                let message = match requires {
+                    true => format!("`{}` requires `{}`", field.key, other_key),
+                    false => format!("`{}` conflicts with `{}`", field.key, other_key),
+                };
+                let allowed = vec![vec![(this, false)], vec![(this, true), (that, requires)]];
+                if requires {
+                    let msg = format!(
+                        "`{1}` requires `{0}`, so it can't be passed as `{0}?:`",
+                        other_key, field.key
+                    );
+                    maybe_errors.push((other_key.clone(), msg));
+                }
+
+                rules.push(Rule { message, allowed });
+            }
+        }
+
+        // a tracked argument can only be passed once
+        for field in optional_args {
+            if field.attrs.repeated && tracked.contains(&field.key) {
+                return Err(Error::new_spanned(
+                    &field.key,
+                    "repeated arguments can't be in a group, requires or conflicts_with",
+                ));
+            }
+        }
+
+        Ok(Self {
+            tracked,
+            rules,
+            maybe_errors,
+        })
+    }
+
+    pub fn is_tracked(&self, key: &Ident) -> bool {
+        self.tracked.contains(key)
+    }
+
+    // why `key?:` isn't allowed for this argument, if it isn't
+    pub fn maybe_error(&self, key: &Ident) -> Option<&str> {
+        self.maybe_errors
+            .iter()
+            .find(|(other, _)| other == key)
+            .map(|(_, msg)| msg.as_str())
+    }
+
+    // the fields behind `tracked`, in the same order
+    pub fn tracked_fields<'f>(&self, optional_args: &'f [BuilderField]) -> Vec<&'f BuilderField> {
+        self.tracked
+            .iter()
+            .filter_map(|key| optional_args.iter().find(|f| &f.key == key))
+            .collect()
+    }
+
+    /*
+    The names of the traits, ie `__Rule0_ylabel_requires_y` for the prefix `__Rule`. Without
+    `#[diagnostic::on_unimplemented]` the name is all the error has to go on, so it spells out
+    the rule.
+    */
+    pub fn idents(&self, prefix: &str) -> Vec<Ident> {
+        self.rules
+            .iter()
+            .enumerate()
+            .map(|(id, rule)| {
+                let mut slug = String::new();
+                for c in rule.message.chars() {
+                    match c.is_ascii_alphanumeric() {
+                        true => slug.push(c),
+                        false if !slug.ends_with('_') => slug.push('_'),
+                        false => {}
+                    }
+                }
+                format_ident!("{}{}_{}", prefix, id, slug.trim_matches('_'))
+            })
+            .collect()
+    }
+
+    pub fn traits(&self, vis: &Visibility, idents: &[Ident]) -> TokenStream2 {
+        let defs = self.rules.iter().zip(idents).map(|(rule, ident)| {
+            let message = &rule.message;
+            let diagnostic = match cfg!(optargs_diagnostic) {
+                true => quote! { #[diagnostic::on_unimplemented(message = #message)] },
+                false => quote! {},
+            };
+            quote! {
+                #[doc(hidden)]
+                #[allow(non_camel_case_types)]
+                #diagnostic
+                #vis trait #ident {}
+            }
+        });
+        quote! { #(#defs)* }
+    }
+
+    // implement the traits at `paths` for the validator or builder `ty`
+    pub fn impls(
+        &self,
+        gen: &GenericGenerator,
+        ty: &TokenStream2,
+        paths: &[TokenStream2],
+    ) -> TokenStream2 {
+        let where_clause = gen.where_clause();
+        let first = gen.first_tracked();
+
+        let mut out = TokenStream2::new();
+        for (rule, path) in self.rules.iter().zip(paths) {
+            for state in &rule.allowed {
+                let fixed: Vec<_> = state.iter().map(|(id, v)| (first + id, *v)).collect();
+                let (impl_generics, ty_generics) = gen.gen_fixed(&fixed);
+                out.extend(quote! {
+                    impl #impl_generics #path for #ty #ty_generics #where_clause {}
+                });
+            }
+        }
+        out
+    }
+
+    /*
+    The rules as runtime checks. `passed` gives a `bool` expression for whether an optional
+    argument was passed, and `fail` the statement reporting a broken rule from its message:
+
+        if !((p0 && !p1) || (!p0 && p1) || (!p0 && !p1)) {
+            return Err(ArgError::Rule { function: "load", message: "only one of ..." });
+        }
+    */
+    pub fn check(
+        &self,
+        optional_args: &[BuilderField],
+        passed: impl Fn(&BuilderField) -> TokenStream2,
+        fail: impl Fn(&str) -> TokenStream2,
+    ) -> TokenStream2 {
+        let passed: Vec<_> = self
+            .tracked_fields(optional_args)
+            .into_iter()
+            .map(passed)
+            .collect();
+
+        let mut out = TokenStream2::new();
+        for rule in &self.rules {
+            let states = rule.allowed.iter().map(|state| {
+                let markers = state.iter().map(|(id, value)| {
+                    let passed = &passed[*id];
+                    match value {
+                        true => quote! { #passed },
+                        false => quote! { !#passed },
+                    }
+                });
+                quote! { (#(#markers)&&*) }
+            });
+            let fail = fail(&rule.message);
+            out.extend(quote! {
+                if !(#(#states)||*) {
+                    #fail
+                }
+            });
+        }
+        out
+    }
+
+    // the bound placed on `build`, ie `where Self: __Rule0 + __Rule1`
+    pub fn bound(&self, paths: &[TokenStream2]) -> TokenStream2 {
+        match paths.is_empty() {
+            true => quote! {},
+            false => quote! { where Self: #(#paths)+* },
+        }
+    }
+}
+
+// the key of the optional argument named in `requires` or `conflicts_with`
+fn find<'a>(optional_args: &'a [BuilderField], key: &LitStr) -> Result<&'a Ident> {
+    optional_args
+        .iter()
+        .map(|field| &field.key)
+        .find(|other| **other == key.value())
+        .ok_or_else(|| {
+            Error::new_spanned(
+                key,
+                format!("there's no optional argument named `{}`", key.value()),
+            )
+        })
+}
+
+struct Group<'a> {
+    name: String,
+
+    // the tracked marker and key of every argument in the group
+    members: Vec<(usize, &'a Ident)>,
+
+    // exactly one instead of at most one
+    required: bool,
+}
+
+// "`a`", "`a` or `b`", "`a`, `b` or `c`"
+fn list<'a>(keys: impl Iterator<Item = &'a Ident>) -> String {
+    let keys: Vec<String> = keys.map(|key| format!("`{}`", key)).collect();
+    match keys.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,341 @@
+//! `#[optstruct(json_schema)]` generates `Struct::json_schema()`.
+//!
+//! The schema describes the struct's JSON form, which is what the `serde` feature reads: required
+//! fields are `required`, unknown keys are rejected, and keys follow `#[optarg(name)]`. It's built
+//! here and ends up in the binary as a single string literal.
+//!
+//! Types are matched by name, so integers, floats, bools, strings, `Vec`s and maps get a proper
+//! schema, and anything else accepts any value. `Option` fields accept `null` as well, like serde.
+//! Defaults are only included when they're literals, `Default::default()` of a known type, or a
+//! `vec![]` of those.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{Expr, GenericArgument, Lit, PathArguments, Token, Type, UnOp};
+
+use crate::field::{option_item, vec_item, BuilderField};
+use crate::optstruct::OptStruct;
+
+pub fn generate(opt: &OptStruct) -> TokenStream2 {
+    let OptStruct {
+        name,
+        vis,
+        generics,
+        required_args,
+        optional_args,
+        doc,
+        ..
+    } = opt;
+
+    let mut properties = Vec::new();
+    for field in required_args.iter() {
+        properties.push((key(field), property(field, true)));
+    }
+    for field in optional_args.iter() {
+        properties.push((key(field), property(field, false)));
+    }
+    let required = required_args.iter().map(|f| Json::Str(key(f))).collect();
+
+    let mut schema = vec![
+        (
+            "$schema".to_string(),
+            Json::Str("https://json-schema.org/draft/2020-12/schema".to_string()),
+        ),
+        ("title".to_string(), Json::Str(name.to_string())),
+    ];
+    if !doc.is_empty() {
+        schema.push(("description".to_string(), Json::Str(doc.clone())));
+    }
+    schema.extend(vec![
+        ("type".to_string(), Json::Str("object".to_string())),
+        ("properties".to_string(), Json::Obj(properties)),
+        ("required".to_string(), Json::Arr(required)),
+        (
+            "additionalProperties".to_string(),
+            Json::Raw("false".to_string()),
+        ),
+    ]);
+
+    let mut out = String::new();
+    Json::Obj(schema).write(&mut out, 0);
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// A JSON Schema for this struct, with its required fields, defaults and docs.
+            #vis fn json_schema() -> ::std::string::String {
+                ::std::string::String::from(#out)
+            }
+        }
+    }
+}
+
+fn key(field: &BuilderField) -> String {
+    field.key.to_string().trim_start_matches("r#").to_string()
+}
+
+// The schema of a single field, ie `{ "type": "integer", "default": 1, "description": "..." }`
+fn property(field: &BuilderField, required: bool) -> Json {
+    let BuilderField { ty, attrs, .. } = field;
+    let or_null = |inner: Json| {
+        let null = Json::Obj(vec![("type".to_string(), Json::Str("null".to_string()))]);
+        vec![("anyOf".to_string(), Json::Arr(vec![inner, null]))]
+    };
+
+    // nullable fields keep their Option<T> in `ty`, while `Option<T>` fields are just `T`. Both
+    // take null, as an explicit null or as a missing value.
+    let mut entries = match (attrs.nullable, required || attrs.default.is_some()) {
+        (true, _) => {
+            or_null(option_item(ty).map_or(Json::Obj(Vec::new()), |item| schema_of(&item)))
+        }
+        (false, false) => or_null(schema_of(ty)),
+        (false, true) => match schema_of(ty) {
+            Json::Obj(entries) => entries,
+            _ => Vec::new(),
+        },
+    };
+
+    if let Some(default) = attrs.default.as_ref().and_then(|d| value_of(d, ty)) {
+        entries.push(("default".to_string(), default));
+    }
+    if !attrs.doc.is_empty() {
+        entries.push(("description".to_string(), Json::Str(attrs.doc.clone())));
+    }
+    if attrs.deprecated.is_some() {
+        entries.push(("deprecated".to_string(), Json::Raw("true".to_string())));
+    }
+
+    Json::Obj(entries)
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Integer { unsigned: bool },
+    Number,
+    Boolean,
+    String,
+    Array,
+    Object,
+    Any,
+}
+
+fn kind_of(ty: &Type) -> (Kind, Vec<Type>) {
+    match ty {
+        Type::Reference(r) => kind_of(&r.elem),
+        Type::Paren(p) => kind_of(&p.elem),
+        Type::Group(g) => kind_of(&g.elem),
+        Type::Slice(s) => (Kind::Array, vec![(*s.elem).clone()]),
+        Type::Array(a) => (Kind::Array, vec![(*a.elem).clone()]),
+        Type::Path(p) if p.qself.is_none() => {
+            let last = match p.path.segments.last() {
+                Some(last) => last,
+                None => return (Kind::Any, Vec::new()),
+            };
+            let args = match &last.arguments {
+                PathArguments::AngleBracketed(args) => args
+                    .args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        GenericArgument::Type(ty) => Some(ty.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            let kind = match last.ident.to_string().as_str() {
+                "i8" | "i16" | "i32" | "i64" | "i128" | "isize" => {
+                    Kind::Integer { unsigned: false }
+                }
+                "u8" | "u16" | "u32" | "u64" | "u128" | "usize" => Kind::Integer { unsigned: true },
+                "f32" | "f64" => Kind::Number,
+                "bool" => Kind::Boolean,
+                "str" | "String" | "char" | "Path" | "PathBuf" | "Cow" => Kind::String,
+                "Vec" | "VecDeque" | "HashSet" | "BTreeSet" => Kind::Array,
+                "HashMap" | "BTreeMap" => Kind::Object,
+
+                // wrappers that serialize as their contents
+                "Option" | "Box" | "Rc" | "Arc" => match args.first() {
+                    Some(inner) => return kind_of(inner),
+                    None => Kind::Any,
+                },
+                _ => Kind::Any,
+            };
+            (kind, args)
+        }
+        _ => (Kind::Any, Vec::new()),
+    }
+}
+
+fn schema_of(ty: &Type) -> Json {
+    let typed = |name: &str| ("type".to_string(), Json::Str(name.to_string()));
+
+    let entries = match kind_of(ty) {
+        (Kind::Integer { unsigned }, _) => {
+            let mut entries = vec![typed("integer")];
+            if unsigned {
+                entries.push(("minimum".to_string(), Json::Raw("0".to_string())));
+            }
+            entries
+        }
+        (Kind::Number, _) => vec![typed("number")],
+        (Kind::Boolean, _) => vec![typed("boolean")],
+        (Kind::String, _) => vec![typed("string")],
+        (Kind::Array, args) => {
+            let items = args.first().map_or(Json::Obj(Vec::new()), schema_of);
+            vec![typed("array"), ("items".to_string(), items)]
+        }
+        (Kind::Object, args) => {
+            let values = args.get(1).map_or(Json::Obj(Vec::new()), schema_of);
+            vec![
+                typed("object"),
+                ("additionalProperties".to_string(), values),
+            ]
+        }
+        (Kind::Any, _) => Vec::new(),
+    };
+
+    Json::Obj(entries)
+}
+
+// The JSON form of a default expression, if it can be worked out without running it
+fn value_of(expr: &Expr, ty: &Type) -> Option<Json> {
+    match expr {
+        Expr::Paren(p) => value_of(&p.expr, ty),
+        Expr::Group(g) => value_of(&g.expr, ty),
+        Expr::Lit(lit) => literal(&lit.lit),
+        Expr::Unary(u) if matches!(u.op, UnOp::Neg(_)) => match value_of(&u.expr, ty)? {
+            Json::Raw(number) if number != "true" && number != "false" => {
+                Some(Json::Raw(format!("-{}", number)))
+            }
+            _ => None,
+        },
+
+        // "text".to_string() and friends
+        Expr::MethodCall(call) if call.args.is_empty() => {
+            let method = call.method.to_string();
+            match ["to_string", "to_owned", "into"].contains(&method.as_str()) {
+                true => value_of(&call.receiver, ty),
+                false => None,
+            }
+        }
+
+        Expr::Call(call) if call.args.is_empty() => match &*call.func {
+            Expr::Path(p) if p.path.segments.last()?.ident == "default" => empty(ty),
+            Expr::Path(p) if p.path.segments.last()?.ident == "new" => match kind_of(ty).0 {
+                Kind::String | Kind::Array | Kind::Object => empty(ty),
+                _ => None,
+            },
+            _ => None,
+        },
+
+        Expr::Array(array) => list(array.elems.iter(), ty),
+        Expr::Macro(mac) if mac.mac.path.is_ident("vec") => {
+            let items = mac
+                .mac
+                .parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated)
+                .ok()?;
+            list(items.iter(), ty)
+        }
+        _ => None,
+    }
+}
+
+fn list<'a>(items: impl Iterator<Item = &'a Expr>, ty: &Type) -> Option<Json> {
+    let item_ty = vec_item(ty).or_else(|| kind_of(ty).1.into_iter().next())?;
+    let items = items.map(|item| value_of(item, &item_ty));
+    items.collect::<Option<Vec<_>>>().map(Json::Arr)
+}
+
+// What Default::default() gives for a type, in JSON
+fn empty(ty: &Type) -> Option<Json> {
+    Some(match kind_of(ty).0 {
+        Kind::Integer { .. } => Json::Raw("0".to_string()),
+        Kind::Number => Json::Raw("0.0".to_string()),
+        Kind::Boolean => Json::Raw("false".to_string()),
+        Kind::String => Json::Str(String::new()),
+        Kind::Array => Json::Arr(Vec::new()),
+        Kind::Object => Json::Obj(Vec::new()),
+        Kind::Any => return None,
+    })
+}
+
+fn literal(lit: &Lit) -> Option<Json> {
+    Some(match lit {
+        Lit::Str(s) => Json::Str(s.value()),
+        Lit::Char(c) => Json::Str(c.value().to_string()),
+        Lit::Bool(b) => Json::Raw(b.value.to_string()),
+        Lit::Int(i) => Json::Raw(i.base10_digits().to_string()),
+        Lit::Float(f) => {
+            // `1.` is fine in rust, but not in JSON
+            let digits = f.base10_digits();
+            match digits.ends_with('.') {
+                true => Json::Raw(format!("{}0", digits)),
+                false => Json::Raw(digits.to_string()),
+            }
+        }
+        _ => return None,
+    })
+}
+
+// Just enough JSON to write a schema, with objects keeping their keys in order
+enum Json {
+    // numbers, booleans and null, written as they are
+    Raw(String),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn write(&self, out: &mut String, indent: usize) {
+        let pad = |out: &mut String, indent: usize| out.push_str(&"  ".repeat(indent));
+
+        match self {
+            Json::Raw(raw) => out.push_str(raw),
+            Json::Str(s) => write_str(out, s),
+            Json::Arr(items) if items.is_empty() => out.push_str("[]"),
+            Json::Obj(entries) if entries.is_empty() => out.push_str("{}"),
+            Json::Arr(items) => {
+                out.push_str("[\n");
+                for (idx, item) in items.iter().enumerate() {
+                    pad(out, indent + 1);
+                    item.write(out, indent + 1);
+                    out.push_str(if idx + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                pad(out, indent);
+                out.push(']');
+            }
+            Json::Obj(entries) => {
+                out.push_str("{\n");
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    pad(out, indent + 1);
+                    write_str(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                    out.push_str(if idx + 1 < entries.len() { ",\n" } else { "\n" });
+                }
+                pad(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
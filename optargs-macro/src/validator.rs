@@ -0,0 +1,343 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, TokenStreamExt};
+use syn::{GenericParam, Generics, Ident, Visibility, WhereClause};
+
+use crate::field::BuilderField;
+
+/*
+This struct lets us generate the correct const generics form depending on the arguments.
+---
+Which arguments were passed is tracked in a single type parameter, a list of const bools, so we
+can turn this function:
+
+    fn blah(a: u32, b: u32){}
+
+into
+
+    impl<__M: Mark<I1>> Builder<__M> {
+                   ^^ -- the position of `b`
+        fn b(self) -> Builder<<__M as Mark<I1>>::Marked> {
+                              ^^^^^^^^^^^^^^^^^^^^^^^^^ -- the same list with `b` set to true
+            ...
+        }
+    }
+
+    impl Builder<Marker<true, Marker<true, End>>> {
+        fn call(self) { ... }
+    }
+
+`Mark` walks down the list to the position, see `optargs::__private`. It only exists while that
+position is still false, so passing a key twice is an error too.
+--
+The validator is emitted once, next to the function or struct, instead of inside every expansion
+of the macro. Every call site then only costs one method call per key it passes:
+
+    plot::Validator::builder().x().title().build();
+
+Each setter only names its own position, so the definition grows linearly with the number of
+arguments as well.
+---
+It's important to keep the original generics. Structs need them so `build` can hand back the
+struct itself, which is what lets the compiler infer them at the call site.
+*/
+pub struct GenericGenerator<'a> {
+    num_args: usize,
+
+    // the last markers belong to optional arguments in a rule, see `crate::rules`, and may end up
+    // either way
+    tracked: usize,
+
+    // generics of the original item, which come before the markers
+    generics: &'a Generics,
+}
+
+impl<'a> GenericGenerator<'a> {
+    pub fn new(num_args: usize, generics: &'a Generics) -> Self {
+        Self {
+            num_args,
+            tracked: 0,
+            generics,
+        }
+    }
+
+    // `num_args` counts the tracked markers too
+    pub fn tracking(mut self, tracked: usize) -> Self {
+        self.tracked = tracked;
+        self
+    }
+
+    // the marker of the first tracked argument
+    pub fn first_tracked(&self) -> usize {
+        self.num_args - self.tracked
+    }
+
+    pub fn where_clause(&self) -> &Option<WhereClause> {
+        &self.generics.where_clause
+    }
+
+    // the original generics with their bounds, followed by `extra`, for the impls
+    fn gen_params(&self, extra: TokenStream2) -> TokenStream2 {
+        let mut inner = quote! {};
+        for param in self.generics.params.iter() {
+            // defaults aren't allowed before the markers, and impls can't have them anyways
+            let param = match param.clone() {
+                GenericParam::Type(mut ty) => {
+                    ty.eq_token = None;
+                    ty.default = None;
+                    GenericParam::Type(ty)
+                }
+                GenericParam::Const(mut c) => {
+                    c.eq_token = None;
+                    c.default = None;
+                    GenericParam::Const(c)
+                }
+                other => other,
+            };
+            inner.append_all(quote! { #param, });
+        }
+        quote! { <#inner #extra> }
+    }
+
+    // the original generics, without their bounds, followed by the markers if there are any
+    fn gen_args(&self, markers: TokenStream2) -> TokenStream2 {
+        let mut inner = quote! {};
+        for param in self.generics.params.iter() {
+            inner.append_all(match param {
+                GenericParam::Type(ty) => {
+                    let ident = &ty.ident;
+                    quote! { #ident, }
+                }
+                GenericParam::Lifetime(lt) => {
+                    let lt = &lt.lifetime;
+                    quote! { #lt, }
+                }
+                GenericParam::Const(c) => {
+                    let ident = &c.ident;
+                    quote! { #ident, }
+                }
+            });
+        }
+        match self.num_args {
+            0 => quote! { <#inner> },
+            _ => quote! { <#inner #markers> },
+        }
+    }
+
+    // the list of markers, with the value of every position
+    /*
+        ::optargs::__private::Marker<true, ::optargs::__private::Marker<M1, ::optargs::__private::End>>
+    */
+    fn gen_list(&self, value: impl Fn(usize) -> TokenStream2) -> TokenStream2 {
+        (0..self.num_args)
+            .rev()
+            .fold(quote! { ::optargs::__private::End }, |rest, id| {
+                let value = value(id);
+                quote! { ::optargs::__private::Marker<#value, #rest> }
+            })
+    }
+
+    // the position of a marker in the list, ie `I2`, nesting `Next` past the named ones
+    fn gen_index(id: usize) -> TokenStream2 {
+        match id {
+            0..=63 => {
+                let index = format_ident!("I{}", id);
+                quote! { ::optargs::__private::#index }
+            }
+            _ => {
+                let prev = Self::gen_index(id - 1);
+                quote! { ::optargs::__private::Next<#prev> }
+            }
+        }
+    }
+
+    // generic over every marker, used in the struct position and for the impls that don't care
+    /*
+        pub struct ExampleBuilder<__M>(Storage, PhantomData<__M>);
+                                 ^^^^^ this bit gets generated
+    */
+    pub fn gen_all_generic(&self) -> TokenStream2 {
+        match self.num_args {
+            0 => self.gen_params(quote! {}),
+            _ => self.gen_params(quote! { __M }),
+        }
+    }
+
+    // the type arguments matching `gen_all_generic`
+    pub fn gen_any(&self) -> TokenStream2 {
+        self.gen_args(quote! { __M })
+    }
+
+    // the field holding on to the markers, after the storage of a tuple struct
+    pub fn marker_field(&self) -> TokenStream2 {
+        match self.num_args {
+            0 => quote! {},
+            _ => quote! { , ::core::marker::PhantomData<__M> },
+        }
+    }
+
+    // the value of `marker_field`
+    pub fn marker_value(&self) -> TokenStream2 {
+        match self.num_args {
+            0 => quote! {},
+            _ => quote! { , ::core::marker::PhantomData },
+        }
+    }
+
+    // the impl of the setter at a position, and the type it goes from
+    /*
+    impl<__M: Mark<I0>> ExampleBuilder<__M> {
+        ^^^^^^^^^^^^^^^ .0            ^^^^^ .1
+    */
+    pub fn gen_marking(&self, position: usize) -> (TokenStream2, TokenStream2) {
+        let index = Self::gen_index(position);
+        (
+            self.gen_params(quote! { __M: ::optargs::__private::Mark<#index> }),
+            self.gen_any(),
+        )
+    }
+
+    // the type the setter at a position goes to
+    /*
+        fn a(self) -> ExampleBuilder<<__M as Mark<I0>>::Marked> {
+                                    ^^^^^^^^^^^^^^^^^^^^^^^^^^^ generate this part
+    */
+    pub fn gen_marked(&self, position: usize) -> TokenStream2 {
+        let index = Self::gen_index(position);
+        self.gen_args(quote! { <__M as ::optargs::__private::Mark<#index>>::Marked })
+    }
+
+    // generic over every marker but the fixed ones, for the impls of rules
+    /*
+    impl<const M1: bool> __Rule0 for ExampleBuilder<Marker<true, Marker<M1, End>>> {}
+        ^^^^^^^^^^^^^^^^ .0                        ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ .1
+    */
+    pub fn gen_fixed(&self, fixed: &[(usize, bool)]) -> (TokenStream2, TokenStream2) {
+        let value = |id| fixed.iter().find(|(at, _)| *at == id).map(|(_, v)| *v);
+
+        let mut params = quote! {};
+        for id in (0..self.num_args).filter(|id| value(*id).is_none()) {
+            let marker = format_ident!("M{}", id);
+            params.append_all(quote! { const #marker: bool, });
+        }
+        let list = self.gen_list(|id| match value(id) {
+            Some(marker) => quote! { #marker },
+            None => {
+                let marker = format_ident!("M{}", id);
+                quote! { #marker }
+            }
+        });
+
+        (self.gen_params(params), self.gen_args(list))
+    }
+
+    // every required argument passed, and any of the tracked ones
+    pub fn gen_done(&self) -> (TokenStream2, TokenStream2) {
+        let fixed: Vec<_> = (0..self.first_tracked()).map(|id| (id, true)).collect();
+        self.gen_fixed(&fixed)
+    }
+
+    /*
+    Generate the validator type and its methods:
+    - `validator` is the name of the type
+    - `phantom` is a type that mentions all of the original generics
+    - `build` is the method that's available once every required argument has been passed
+    - `marked` are the arguments with a marker, the required ones followed by the tracked ones
+    */
+    pub fn generate<'f>(
+        &self,
+        vis: &Visibility,
+        validator: &Ident,
+        phantom: TokenStream2,
+        build: TokenStream2,
+        marked: &[&BuilderField],
+        unmarked: impl IntoIterator<Item = &'f BuilderField>,
+    ) -> TokenStream2 {
+        let where_clause = &self.generics.where_clause;
+
+        let impl_generics = self.gen_all_generic();
+        let any = self.gen_any();
+        let start = self.start();
+        let impl_original = self.gen_all_generic_original();
+        let marker_field = self.marker_field();
+        let marker_value = self.marker_value();
+        let builder_builder = quote! {
+            #[doc(hidden)]
+            #[allow(dead_code, non_camel_case_types)]
+            #vis struct #validator #impl_generics (::core::marker::PhantomData<#phantom> #marker_field) #where_clause;
+
+            #[allow(dead_code)]
+            impl #impl_original #validator #start #where_clause {
+                #vis fn builder() -> Self {
+                    #validator(::core::marker::PhantomData #marker_value)
+                }
+            }
+        };
+
+        let mut builders = TokenStream2::new();
+        for (id, field) in marked.iter().enumerate() {
+            let name = &field.key;
+            let deprecation = field.deprecation();
+            let (impl_generics, ty_gen_in) = self.gen_marking(id);
+            let ty_gen_out = self.gen_marked(id);
+            builders.append_all(quote! {
+                #[allow(dead_code)]
+                impl #impl_generics #validator #ty_gen_in #where_clause {
+                    #[allow(clippy::wrong_self_convention)]
+                    #deprecation
+                    #vis fn #name(self) -> #validator #ty_gen_out {
+                        #validator(::core::marker::PhantomData #marker_value)
+                    }
+                }
+            })
+        }
+
+        let mut optionals = TokenStream2::new();
+        for field in unmarked {
+            let name = &field.key;
+            let deprecation = field.deprecation();
+            optionals.append_all(quote! {
+                #[allow(clippy::wrong_self_convention)]
+                #deprecation
+                #vis fn #name(self) -> Self { self }
+            })
+        }
+        builders.append_all(quote! {
+            #[allow(dead_code)]
+            impl #impl_generics #validator #any #where_clause {
+                #optionals
+            }
+        });
+
+        let (impl_done, ty_done) = self.gen_done();
+        let caller = quote! {
+            #[allow(dead_code)]
+            impl #impl_done #validator #ty_done #where_clause {
+                #build
+            }
+        };
+
+        quote! {
+            #builder_builder
+            #builders
+            #caller
+        }
+    }
+
+    // the validator before any argument has been passed
+    pub fn start(&self) -> TokenStream2 {
+        self.gen_args(self.start_markers())
+    }
+
+    // just the markers of `start`, for types that pick their other arguments themselves
+    pub fn start_markers(&self) -> TokenStream2 {
+        match self.num_args {
+            0 => quote! {},
+            _ => self.gen_list(|_| quote! { false }),
+        }
+    }
+
+    // the original generics with bounds but no markers, for impls on fully-specified validators
+    pub fn gen_all_generic_original(&self) -> TokenStream2 {
+        GenericGenerator::new(0, self.generics).gen_all_generic()
+    }
+}
@@ -0,0 +1,294 @@
+//! Runtime support for `#[optstruct(argv)]`.
+//!
+//! The derive generates a table of [`Flag`]s for the struct and leans on [`parse`] to split the
+//! command line into raw values. Each value is then converted with `FromStr` by the generated code.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A single `--flag` accepted by a generated `from_argv`.
+#[derive(Debug, Clone, Copy)]
+pub struct Flag {
+    /// The flag as written on the command line, without the leading `--`
+    pub name: &'static str,
+
+    /// The placeholder shown in the help text, ie `--title <TITLE>`
+    pub value: &'static str,
+
+    /// Switches don't take a value, their presence means `true`
+    pub switch: bool,
+
+    pub required: bool,
+
+    /// The doc comment of the field
+    pub help: &'static str,
+}
+
+/// Everything that can go wrong while reading the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgvError {
+    /// `--help` or `-h` was passed. Contains the generated help text.
+    Help(String),
+
+    /// A flag that isn't a field of the struct
+    Unknown(String),
+
+    /// A required flag wasn't passed
+    Missing(&'static str),
+
+    /// A flag that takes a value was the last argument
+    NoValue(&'static str),
+
+    /// A flag was passed more than once
+    Duplicate(&'static str),
+
+    /// The value couldn't be parsed with `FromStr`
+    Invalid {
+        flag: &'static str,
+        value: String,
+        reason: String,
+    },
+
+    /// The flags that were passed break a `group`, `requires` or `conflicts_with` rule
+    Rule(&'static str),
+}
+
+impl ArgvError {
+    /// Print the help text or error and exit the process, like most CLI tools do.
+    pub fn exit(self) -> ! {
+        match self {
+            ArgvError::Help(help) => {
+                println!("{}", help);
+                std::process::exit(0)
+            }
+            err => {
+                eprintln!("error: {}", err);
+                std::process::exit(2)
+            }
+        }
+    }
+}
+
+impl Display for ArgvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgvError::Help(help) => f.write_str(help),
+            ArgvError::Unknown(arg) => write!(f, "unexpected argument '{}'", arg),
+            ArgvError::Missing(flag) => write!(f, "missing required argument '--{}'", flag),
+            ArgvError::NoValue(flag) => write!(f, "'--{}' requires a value", flag),
+            ArgvError::Duplicate(flag) => write!(f, "'--{}' was passed more than once", flag),
+            ArgvError::Invalid {
+                flag,
+                value,
+                reason,
+            } => write!(f, "invalid value '{}' for '--{}': {}", value, flag, reason),
+            ArgvError::Rule(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for ArgvError {}
+
+/// Split the command line into one raw value per flag, in the same order as `flags`.
+///
+/// The first item is the program name, just like `std::env::args()`. Values are passed as
+/// `--key=value` or `--key value`, and switches as a bare `--key`.
+///
+/// ```rust
+/// use optargs::argv::{parse, ArgvError, Flag};
+///
+/// const FLAGS: &[Flag] = &[
+///     Flag { name: "title", value: "TITLE", switch: false, required: true, help: "" },
+///     Flag { name: "scale", value: "SCALE", switch: false, required: false, help: "" },
+///     Flag { name: "legend", value: "LEGEND", switch: true, required: false, help: "" },
+/// ];
+///
+/// let values = parse("", FLAGS, vec!["plot", "--title=hi", "--scale", "2", "--legend"]).unwrap();
+/// assert_eq!(values, [Some("hi".to_string()), Some("2".to_string()), Some("true".to_string())]);
+///
+/// // only the keys that were passed have a value, `required` checks the rest
+/// assert_eq!(parse("", FLAGS, vec!["plot"]).unwrap(), [None, None, None]);
+///
+/// // a value may contain `=` and may start with a dash
+/// let values = parse("", FLAGS, vec!["plot", "--title=a=b", "--scale", "-1"]).unwrap();
+/// assert_eq!(values[..2], [Some("a=b".to_string()), Some("-1".to_string())]);
+///
+/// assert_eq!(
+///     parse("", FLAGS, vec!["plot", "--title", "a", "--title", "b"]),
+///     Err(ArgvError::Duplicate("title"))
+/// );
+/// assert_eq!(parse("", FLAGS, vec!["plot", "--title"]), Err(ArgvError::NoValue("title")));
+/// assert_eq!(
+///     parse("", FLAGS, vec!["plot", "--color", "red"]),
+///     Err(ArgvError::Unknown("--color".to_string()))
+/// );
+/// assert!(matches!(parse("", FLAGS, vec!["plot", "-h"]), Err(ArgvError::Help(_))));
+/// ```
+///
+/// `--` ends the options. Nothing after it is read as a flag, and since there are no positional
+/// arguments, anything after it is unexpected:
+///
+/// ```rust
+/// # use optargs::argv::{parse, ArgvError, Flag};
+/// # const FLAGS: &[Flag] = &[
+/// #     Flag { name: "legend", value: "LEGEND", switch: true, required: false, help: "" },
+/// # ];
+/// assert_eq!(parse("", FLAGS, vec!["plot", "--legend", "--"]).unwrap(), [Some("true".to_string())]);
+/// assert_eq!(
+///     parse("", FLAGS, vec!["plot", "--", "--help"]),
+///     Err(ArgvError::Unknown("--help".to_string()))
+/// );
+/// ```
+pub fn parse<I, S>(about: &str, flags: &[Flag], args: I) -> Result<Vec<Option<String>>, ArgvError>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut args = args.into_iter().map(Into::into);
+    let bin = args.next().unwrap_or_default();
+
+    let mut values = vec![None; flags.len()];
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return match args.next() {
+                Some(arg) => Err(ArgvError::Unknown(arg)),
+                None => Ok(values),
+            };
+        }
+
+        if arg == "--help" || arg == "-h" {
+            return Err(ArgvError::Help(help(&bin, about, flags)));
+        }
+
+        let (name, inline) = match arg.strip_prefix("--") {
+            Some(rest) => match rest.find('=') {
+                Some(idx) => (&rest[..idx], Some(rest[idx + 1..].to_string())),
+                None => (rest, None),
+            },
+            None => return Err(ArgvError::Unknown(arg)),
+        };
+
+        let id = match flags.iter().position(|f| f.name == name) {
+            Some(id) => id,
+            None => return Err(ArgvError::Unknown(arg)),
+        };
+        let flag = &flags[id];
+
+        let value = match (inline, flag.switch) {
+            (Some(value), _) => value,
+            (None, true) => "true".to_string(),
+            (None, false) => args.next().ok_or(ArgvError::NoValue(flag.name))?,
+        };
+
+        if values[id].replace(value).is_some() {
+            return Err(ArgvError::Duplicate(flag.name));
+        }
+    }
+
+    Ok(values)
+}
+
+/// Convert the raw value of a required flag.
+pub fn required<T>(flag: &Flag, value: Option<String>) -> Result<T, ArgvError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    optional(flag, value)?.ok_or(ArgvError::Missing(flag.name))
+}
+
+/// Convert the raw value of an optional flag.
+pub fn optional<T>(flag: &Flag, value: Option<String>) -> Result<Option<T>, ArgvError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .map(|value| {
+            T::from_str(&value).map_err(|e| ArgvError::Invalid {
+                flag: flag.name,
+                reason: e.to_string(),
+                value,
+            })
+        })
+        .transpose()
+}
+
+/// Generate the `--help` text for a set of flags.
+///
+/// Required flags are listed in the usage line too, and every flag's help is lined up in a
+/// column after the widest flag.
+///
+/// ```rust
+/// use optargs::argv::{help, Flag};
+///
+/// const FLAGS: &[Flag] = &[
+///     Flag { name: "x", value: "X", switch: false, required: true, help: "Points to plot" },
+///     Flag { name: "title", value: "TITLE", switch: false, required: false, help: "Shown above" },
+///     Flag { name: "legend", value: "LEGEND", switch: true, required: false, help: "" },
+/// ];
+///
+/// assert_eq!(
+///     help("/usr/bin/plot", "Draw a plot", FLAGS),
+///     "Usage: plot [OPTIONS] --x <X>
+///
+/// Draw a plot
+///
+/// Options:
+///   --x <X>          Points to plot [required]
+///   --title <TITLE>  Shown above
+///   --legend
+///   -h, --help       Print help"
+/// );
+/// ```
+pub fn help(bin: &str, about: &str, flags: &[Flag]) -> String {
+    let bin = std::path::Path::new(bin)
+        .file_name()
+        .and_then(|f| f.to_str())
+        .unwrap_or(bin);
+
+    let mut usage = format!("Usage: {} [OPTIONS]", bin);
+    for flag in flags.iter().filter(|f| f.required) {
+        usage += &format!(" --{} <{}>", flag.name, flag.value);
+    }
+
+    let columns: Vec<(String, String)> = flags
+        .iter()
+        .map(|flag| {
+            let left = match flag.switch {
+                true => format!("--{}", flag.name),
+                false => format!("--{} <{}>", flag.name, flag.value),
+            };
+            let right = match flag.required {
+                true => format!("{} [required]", flag.help).trim_start().to_string(),
+                false => flag.help.to_string(),
+            };
+            (left, right)
+        })
+        .chain(std::iter::once((
+            "-h, --help".to_string(),
+            "Print help".to_string(),
+        )))
+        .collect();
+
+    let width = columns
+        .iter()
+        .map(|(left, _)| left.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = usage;
+    if !about.is_empty() {
+        out += "\n\n";
+        out += about;
+    }
+    out += "\n\nOptions:";
+    for (left, right) in columns {
+        let line = format!("  {:width$}  {}", left, right, width = width);
+        out += "\n";
+        out += line.trim_end();
+    }
+    out
+}
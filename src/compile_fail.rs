@@ -0,0 +1,323 @@
+//! Calls and declarations the macros must reject at compile time.
+//!
+//! Only built for doctests, each item holds the cases for one kind of mistake.
+
+/// Keys passed next to a spread are still checked at compile time, only the required arguments
+/// may be left to the spread.
+///
+/// ```compile_fail,E0599
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// plot!(x: vec![1], x: vec![2], ..PlotArgs { title: Some("t"), ..Default::default() });
+/// ```
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// plot!(title: "t", ..PlotArgs { x: Some(vec![1]), ..Default::default() });
+/// plot!(x: vec![1], ..PlotArgs { title: Some("t"), ..Default::default() });
+/// ```
+///
+/// ```should_panic
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// plot!(title: "t", ..PlotArgs::default());
+/// ```
+pub struct SpreadKeys;
+
+/// `env` values are parsed with `FromStr`, so they can't be borrowed.
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn plot(#[optarg(env = "PLOT_TITLE")] title: Option<&str>) {}
+/// ```
+///
+/// ```compile_fail
+/// #[derive(optargs::OptStruct)]
+/// pub struct Scatter<'a> {
+///     #[optarg(env = "SCATTER_TITLE", default = "untitled")]
+///     title: &'a str,
+/// }
+/// ```
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn plot(#[optarg(env = "PLOT_TITLE")] title: Option<String>) {}
+/// ```
+pub struct EnvBorrowed;
+
+/// An `Option<impl Trait>` that's left out falls back to `Box<dyn Trait>`, so the box has to
+/// implement the trait.
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn greet(x: i32, name: Option<impl AsRef<str>>) {}
+/// ```
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn greet(x: i32, name: Option<impl Into<String>>) {}
+/// ```
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn greet(x: i32, name: Option<impl std::fmt::Display + std::fmt::Debug>) {}
+/// ```
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn greet(x: i32, name: Option<impl std::fmt::Display + Send>, f: Option<impl Fn(i32) -> i32>) {}
+///
+/// greet!(x: 1);
+/// ```
+pub struct ImplFallback;
+
+/// Groups are checked at compile time, by the macro as well as `call!` and `make!`. A broken group
+/// is a rule trait the validator doesn't implement, E0277.
+///
+/// ```compile_fail,E0277
+/// #[optargs::optfn]
+/// fn load(
+///     #[optarg(group = "source", exclusive)] path: Option<&str>,
+///     #[optarg(group = "source", exclusive)] url: Option<&str>,
+/// ) {}
+///
+/// load!(path: "a", url: "b");
+/// ```
+///
+/// ```compile_fail,E0277
+/// #[optargs::optfn]
+/// fn load(
+///     #[optarg(group = "source", required)] path: Option<&str>,
+///     #[optarg(group = "source", required)] url: Option<&str>,
+/// ) {}
+///
+/// load!();
+/// ```
+///
+/// ```compile_fail,E0277
+/// #[optargs::optfn]
+/// fn load(
+///     #[optarg(group = "source", exclusive)] path: Option<&str>,
+///     #[optarg(group = "source", exclusive)] url: Option<&str>,
+/// ) {}
+///
+/// optargs::call!(load, path: "a", url: "b");
+/// ```
+///
+/// ```compile_fail,E0277
+/// #[derive(optargs::OptStruct)]
+/// pub struct Load {
+///     #[optarg(group = "source", required)]
+///     path: Option<&'static str>,
+///     #[optarg(group = "source", required)]
+///     url: Option<&'static str>,
+/// }
+///
+/// let _ = optargs::make!(Load, path: "a", url: "b");
+/// ```
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn load(
+///     #[optarg(group = "source", required)] path: Option<&str>,
+///     #[optarg(group = "source", required)] url: Option<&str>,
+/// ) {}
+///
+/// load!(url: "b");
+/// ```
+pub struct Groups;
+
+/// A tracked key can only be passed once, since the validator has no method left for it (E0599),
+/// and can't be repeated.
+///
+/// ```compile_fail,E0599
+/// #[optargs::optfn]
+/// fn load(
+///     #[optarg(group = "source", exclusive)] path: Option<&str>,
+///     #[optarg(group = "source", exclusive)] url: Option<&str>,
+/// ) {}
+///
+/// load!(path: "a", path: "b");
+/// ```
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn load(
+///     #[optarg(group = "source", exclusive, repeated)] paths: Vec<&str>,
+///     #[optarg(group = "source", exclusive)] url: Option<&str>,
+/// ) {}
+/// ```
+pub struct GroupRepeated;
+
+/// `requires` and `conflicts_with` are checked at compile time too, as rule traits like groups
+/// (E0277).
+///
+/// ```compile_fail,E0277
+/// #[optargs::optfn]
+/// fn chart(y: Option<Vec<f64>>, #[optarg(requires = "y")] ylabel: Option<&str>) {}
+///
+/// chart!(ylabel: "speed");
+/// ```
+///
+/// ```compile_fail,E0277
+/// #[optargs::optfn]
+/// fn chart(bar: Option<bool>, #[optarg(conflicts_with = "bar")] log_scale: Option<bool>) {}
+///
+/// chart!(bar: true, log_scale: true);
+/// ```
+///
+/// ```compile_fail,E0277
+/// #[derive(optargs::OptStruct)]
+/// pub struct Chart {
+///     y: Option<Vec<f64>>,
+///     #[optarg(requires = "y")]
+///     ylabel: Option<&'static str>,
+/// }
+///
+/// let _ = Chart!(ylabel: "speed");
+/// ```
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn chart(#[optarg(requires = "ylabel")] ylabel: Option<&str>) {}
+/// ```
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn chart(#[optarg(requires = "missing")] ylabel: Option<&str>) {}
+/// ```
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn chart(
+///     y: Option<Vec<f64>>,
+///     #[optarg(requires = "y")] ylabel: Option<&str>,
+///     bar: Option<bool>,
+///     #[optarg(conflicts_with = "bar")] log_scale: Option<bool>,
+/// ) {}
+///
+/// chart!(y: vec![1.0], ylabel: "speed", log_scale: true);
+/// ```
+pub struct Dependencies;
+
+/// `key?:` passes an `Option`, which only works for keys that may be left out.
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// plot!(x?: Some(vec![1]));
+/// ```
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn load(
+///     #[optarg(group = "source", required)] path: Option<&str>,
+///     #[optarg(group = "source", required)] url: Option<&str>,
+/// ) {}
+///
+/// load!(path?: Some("a"));
+/// ```
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn chart(y: Option<Vec<f64>>, #[optarg(requires = "y")] ylabel: Option<&str>) {}
+///
+/// chart!(ylabel: "speed", y?: None);
+/// ```
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// plot!(x: vec![1], title?: None);
+/// ```
+pub struct MaybeRequired;
+
+/// `from_argv` reads one value per flag, so fields that take several can't be read.
+///
+/// ```compile_fail
+/// #[derive(optargs::OptStruct)]
+/// #[optstruct(argv)]
+/// pub struct Cli {
+///     #[optarg(repeated)]
+///     tags: Vec<String>,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(optargs::OptStruct)]
+/// #[optstruct(argv)]
+/// pub struct Cli {
+///     #[optarg(nullable)]
+///     email: Option<Option<String>>,
+/// }
+/// ```
+///
+/// ```rust
+/// #[derive(optargs::OptStruct)]
+/// #[optstruct(argv)]
+/// pub struct Cli {
+///     tags: Option<String>,
+/// }
+/// ```
+pub struct ArgvRepeated;
+
+/// Calls that aren't made of `key: value`, `key?: value`, `key` and a trailing `..args` are
+/// rejected with an error instead of recursing until the limit.
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn plot(x: i32) {}
+///
+/// plot!(x = 1);
+/// ```
+///
+/// ```compile_fail
+/// #[derive(optargs::OptStruct)]
+/// pub struct S {
+///     x: i32,
+/// }
+///
+/// let _ = S!(x = 1);
+/// ```
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// plot!(..PlotArgs::default(), x: vec![1]);
+/// ```
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// let title = Some("squares");
+/// plot!(x: vec![1], title?: title);
+/// plot!(x: vec![1], ..PlotArgs::default());
+/// ```
+pub struct Malformed;
+
+/// `key?:` isn't supported next to `..args`, put the `Option` in the spread instead.
+///
+/// ```compile_fail
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// let title = Some("squares");
+/// plot!(x: vec![1], title?: title, ..PlotArgs::default());
+/// ```
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) {}
+///
+/// let title = Some("squares");
+/// plot!(x: vec![1], ..PlotArgs { title, ..Default::default() });
+/// ```
+pub struct MaybeSpread;
@@ -0,0 +1,112 @@
+//! Runtime support for `#[optarg(env = "VAR")]`.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::mem;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An environment variable that is set, but doesn't parse as its argument's type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvError {
+    /// The name of the variable
+    pub key: String,
+
+    /// The value it's set to
+    pub value: String,
+
+    /// Why `FromStr` rejected it
+    pub reason: String,
+}
+
+impl Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid value {:?} for environment variable {}: {}",
+            self.value, self.key, self.reason
+        )
+    }
+}
+
+impl Error for EnvError {}
+
+type Hook = fn(&EnvError);
+
+// The address of the hook, or 0 while there's none, since a fn pointer is never null
+static HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Read and parse an environment variable, telling an unset variable apart from an invalid one.
+///
+/// ```rust
+/// std::env::set_var("OPTARGS_DOC_TRY_RETRIES", "3");
+/// assert_eq!(optargs::env::try_var::<u32>("OPTARGS_DOC_TRY_RETRIES"), Ok(Some(3)));
+/// assert_eq!(optargs::env::try_var::<u32>("OPTARGS_DOC_TRY_MISSING"), Ok(None));
+///
+/// std::env::set_var("OPTARGS_DOC_TRY_INVALID", "three");
+/// let err = optargs::env::try_var::<u32>("OPTARGS_DOC_TRY_INVALID").unwrap_err();
+/// assert_eq!(err.value, "three");
+/// ```
+pub fn try_var<T>(key: &str) -> Result<Option<T>, EnvError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = match std::env::var(key) {
+        Ok(value) => value,
+        Err(_) => return Ok(None),
+    };
+    match value.parse() {
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(e) => Err(EnvError {
+            key: key.to_string(),
+            reason: e.to_string(),
+            value,
+        }),
+    }
+}
+
+/// Read and parse an environment variable for an argument that was left out at the call site.
+///
+/// Unset variables are treated as missing. A variable that is set but doesn't parse is most likely
+/// a configuration mistake, but it's read in the middle of an ordinary call, where there's no way
+/// to return an error. So it's passed to the hook set with [`set_invalid_hook`], if any, and
+/// treated as missing too, leaving the argument to `None` or its default.
+///
+/// ```rust
+/// std::env::set_var("OPTARGS_DOC_RETRIES", "3");
+/// assert_eq!(optargs::env::var::<u32>("OPTARGS_DOC_RETRIES"), Some(3));
+/// assert_eq!(optargs::env::var::<u32>("OPTARGS_DOC_MISSING"), None);
+///
+/// std::env::set_var("OPTARGS_DOC_INVALID", "three");
+/// assert_eq!(optargs::env::var::<u32>("OPTARGS_DOC_INVALID"), None);
+/// ```
+pub fn var<T>(key: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    try_var(key).unwrap_or_else(|err| {
+        let hook = HOOK.load(Ordering::Acquire);
+        if hook != 0 {
+            // SAFETY: set_invalid_hook only stores addresses of a `Hook`
+            let hook = unsafe { mem::transmute::<usize, Hook>(hook) };
+            hook(&err);
+        }
+        None
+    })
+}
+
+/// Get told about environment variables that are set but don't parse, which [`var`] otherwise
+/// ignores silently. Typically set once at startup to log a warning or abort.
+///
+/// ```rust
+/// fn warn(err: &optargs::env::EnvError) {
+///     eprintln!("warning: ignoring {}", err);
+/// }
+///
+/// optargs::env::set_invalid_hook(warn);
+/// ```
+pub fn set_invalid_hook(hook: fn(&EnvError)) {
+    HOOK.store(hook as usize, Ordering::Release);
+}
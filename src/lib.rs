@@ -1,19 +1,19 @@
-use optargs_macro;
-
 /// Optional arguments for functions!
 /// Add optfn on top of any function and then you can call the funtion with optional arguments.
 ///
 /// Note that this still obeys traditional macro_rules, so you can only use the macro *after* declaration or import it from "crate".
+/// [`call!`] doesn't have that restriction.
 ///
+/// The generated macro is documented with the function's docs and a list of its keys. Use `#[optfn(doc = false)]` to hide it.
 ///
 /// ```ignore
 /// #[optargs::optfn]
 /// pub fn plot(
 ///     x: Vec<i32>,
 ///     y: Option<Vec<i32>>,
-///     title: Option<&str>,
-///     xlabel: Option<&str>,
-///     ylabel: Option<&str>,
+///     title: Option<&'static str>,
+///     xlabel: Option<&'static str>,
+///     ylabel: Option<&'static str>,
 ///     legend: Option<bool>
 /// ) {}
 ///
@@ -28,19 +28,23 @@ use optargs_macro;
 /// ```
 pub use optargs_macro::optfn;
 
+#[doc(hidden)]
+pub use optargs_macro::__OptFnCfg;
+
 /// Flexible struct builder with optional arguments
 /// Derive OptStruct for your structs and then call the Struct's name as a macro to build it, eliding optionals.
 ///
 /// Note that this still obeys traditional macro_rules, so you can only use the macro *after* declaration or import it from "crate".
+/// [`make!`] doesn't have that restriction.
 ///
 /// ```rust
 /// #[derive(optargs::OptStruct)]
 /// pub struct Scatter {
 ///     x: Vec<i32>,
 ///     y: Option<Vec<i32>>,
-///     title: Option<&str>,
-///     xlabel: Option<&str>,
-///     ylabel: Option<&str>,
+///     title: Option<&'static str>,
+///     xlabel: Option<&'static str>,
+///     ylabel: Option<&'static str>,
 ///     legend: Option<bool>
 /// }
 ///
@@ -50,3 +54,389 @@ pub use optargs_macro::optfn;
 /// };
 /// ```
 pub use optargs_macro::OptStruct;
+
+/// Call an optfn without its generated macro.
+///
+/// `call!(plot, x: v)` does the same as `plot!(x: v)`, but finds `plot` through ordinary path
+/// resolution instead of macro_rules scoping. It works before the function is declared, with a path
+/// like `call!(charts::plot, ...)`, and with `use` imports, since importing `plot` brings along
+/// everything `call!` needs.
+///
+/// Keys are written as `key: value` or `key`. Functions with a rest argument take positional
+/// arguments after the keys, though a bare identifier is always read as a key.
+///
+/// ```rust
+/// fn main() {
+///     // plot is declared below
+///     let len = optargs::call!(plot, x: vec![1, 2, 3], title: "hi");
+///     assert_eq!(len, 5);
+/// }
+///
+/// #[optargs::optfn]
+/// fn plot(x: Vec<i32>, title: Option<&str>) -> usize {
+///     x.len() + title.map_or(0, str::len)
+/// }
+/// ```
+pub use optargs_macro::call;
+
+/// Build an OptStruct without its generated macro.
+///
+/// `make!(Scatter, x: v)` does the same as `Scatter!(x: v)`, but finds `Scatter` through ordinary
+/// path resolution, like [`call!`].
+///
+/// ```rust
+/// mod shapes {
+///     #[derive(optargs::OptStruct)]
+///     pub struct Scatter {
+///         pub x: Vec<i32>,
+///         pub title: Option<&'static str>,
+///     }
+/// }
+///
+/// let scatter = optargs::make!(shapes::Scatter, x: vec![1, 2, 3]);
+/// assert_eq!(scatter.title, None);
+/// ```
+pub use optargs_macro::make;
+
+pub mod argv;
+#[cfg(doctest)]
+mod compile_fail;
+pub mod env;
+pub mod parse;
+pub mod registry;
+
+pub use registry::{ArgError, Registration, Registry};
+
+/// An explicit null for `#[optarg(nullable)]` arguments.
+///
+/// A nullable `Option<Option<T>>` is `None` when its key is left out, and `Some(None)` when it's
+/// passed as `key: null` or `key: None`. `null` is only in scope inside the call.
+///
+/// ```rust
+/// #[derive(optargs::OptStruct)]
+/// pub struct Patch {
+///     #[optarg(nullable)]
+///     email: Option<Option<String>>,
+/// }
+///
+/// assert_eq!(Patch!().email, None);
+/// assert_eq!(Patch!(email: null).email, Some(None));
+/// assert_eq!(Patch!(email: "a@b.c".to_string()).email, Some(Some("a@b.c".to_string())));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Null;
+
+/// What the macros know about a single argument, for help text, GUIs and tests.
+///
+/// Every optfn has a `plot::ARGS` and every OptStruct a `Scatter::ARGS`, listing the required
+/// arguments first and then the optional ones, followed by a `rest` argument if there is one.
+///
+/// ```rust
+/// #[optargs::optfn]
+/// fn plot(
+///     x: Vec<i32>,
+///     /// Shown above the plot
+///     #[optarg(default = "untitled")]
+///     title: &str,
+///     #[optarg(default = 1<<4)] width: u32,
+///     #[optarg(default = if 2> 1 { 3 } else { 4 })] height: u32,
+///     #[optarg(default = [1, 2].len() as u8 &3)] margin: u8,
+///     labels: Option<Vec<&'static str>>,
+/// ) {}
+///
+/// assert_eq!(plot::ARGS[0].name, "x");
+/// assert_eq!(plot::ARGS[0].type_name, "Vec<i32>");
+/// assert!(plot::ARGS[0].required);
+/// assert_eq!(plot::ARGS[1].default, Some("\"untitled\""));
+/// assert_eq!(plot::ARGS[1].doc, "Shown above the plot");
+///
+/// // spaced out the usual way, whichever way they were written
+/// assert_eq!(plot::ARGS[2].default, Some("1 << 4"));
+/// assert_eq!(plot::ARGS[3].default, Some("if 2 > 1 { 3 } else { 4 }"));
+/// assert_eq!(plot::ARGS[4].default, Some("[1, 2].len() as u8 & 3"));
+/// assert_eq!(plot::ARGS[5].type_name, "Vec<&'static str>");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArgInfo {
+    /// The key used at the call site
+    pub name: &'static str,
+
+    /// The type passed at the call site as written in the source, ie `i32` for an `Option<i32>`
+    pub type_name: &'static str,
+
+    pub required: bool,
+
+    /// The default expression as written in the source
+    pub default: Option<&'static str>,
+
+    /// The doc comment of the parameter or field
+    pub doc: &'static str,
+}
+
+/// A `group`, `requires` or `conflicts_with` rule that's broken by the fields set at runtime.
+///
+/// `check` reports it on its own, and `finalize` and the builder's `build` return it as a
+/// [`BuildError::Rule`] once the required fields are all there.
+///
+/// ```rust
+/// #[derive(optargs::OptStruct)]
+/// #[optstruct(partial)]
+/// pub struct Scatter {
+///     x: Vec<i32>,
+///     #[optarg(group = "title", exclusive)]
+///     title: Option<String>,
+///     #[optarg(group = "title", exclusive)]
+///     auto_title: Option<bool>,
+/// }
+///
+/// let both = PartialScatter {
+///     x: Some(vec![1]),
+///     title: Some("hi".to_string()),
+///     auto_title: Some(true),
+/// };
+/// assert_eq!(
+///     both.check().unwrap_err().to_string(),
+///     "`Scatter` can't be built: only one of `title` or `auto_title` can be passed (group `title`)"
+/// );
+/// assert!(matches!(both.finalize(), Err(optargs::BuildError::Rule(_))));
+///
+/// let one = PartialScatter {
+///     x: Some(vec![1]),
+///     auto_title: Some(true),
+///     ..Default::default()
+/// };
+/// assert!(one.check().is_ok());
+/// assert!(one.finalize().is_ok());
+/// ```
+///
+/// Builders from `#[optstruct(builder)]` work the same way:
+///
+/// ```rust
+/// #[derive(optargs::OptStruct)]
+/// #[optstruct(builder)]
+/// pub struct Scatter {
+///     x: Vec<i32>,
+///     y: Option<Vec<i32>>,
+///     #[optarg(requires = "y")]
+///     ylabel: Option<String>,
+/// }
+///
+/// let builder = Scatter::builder().ylabel("speed".to_string());
+/// assert_eq!(builder.check().unwrap_err().message, "`ylabel` requires `y`");
+/// assert_eq!(
+///     builder.build().err().unwrap().to_string(),
+///     "`Scatter` is missing the required field `x`"
+/// );
+///
+/// let builder = Scatter::builder().x(vec![1]).y(vec![2]).ylabel("speed".to_string());
+/// assert!(builder.check().is_ok());
+/// assert!(builder.build().is_ok());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BrokenRule {
+    /// The struct being built
+    pub name: &'static str,
+
+    /// The same message the macro gives at compile time
+    pub message: &'static str,
+}
+
+impl std::fmt::Display for BrokenRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` can't be built: {}", self.name, self.message)
+    }
+}
+
+impl std::error::Error for BrokenRule {}
+
+/// The required fields that were still missing when a struct was built at runtime.
+///
+/// ```rust
+/// #[derive(optargs::OptStruct)]
+/// #[optstruct(partial)]
+/// pub struct Scatter {
+///     x: Vec<i32>,
+///     y: Vec<i32>,
+/// }
+///
+/// let err = PartialScatter::default().finalize().err().unwrap();
+/// assert_eq!(err.to_string(), "`Scatter` is missing the required fields `x`, `y`");
+/// assert!(matches!(err, optargs::BuildError::Missing(_)));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingFields {
+    /// The struct being built
+    pub name: &'static str,
+
+    /// The keys of every missing field, in declaration order
+    pub fields: Vec<&'static str>,
+}
+
+impl std::fmt::Display for MissingFields {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields: Vec<String> = self.fields.iter().map(|k| format!("`{}`", k)).collect();
+        match fields.len() {
+            1 => write!(
+                f,
+                "`{}` is missing the required field {}",
+                self.name, fields[0]
+            ),
+            _ => write!(
+                f,
+                "`{}` is missing the required fields {}",
+                self.name,
+                fields.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MissingFields {}
+
+/// Why a struct couldn't be built at runtime by `finalize` or the builder's `build`.
+///
+/// The required fields are checked first, so a broken rule is only reported once they're all set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// Required fields were left out
+    Missing(MissingFields),
+
+    /// The fields that were set break a `group`, `requires` or `conflicts_with` rule
+    Rule(BrokenRule),
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::Missing(missing) => missing.fmt(f),
+            BuildError::Rule(broken) => broken.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<MissingFields> for BuildError {
+    fn from(missing: MissingFields) -> Self {
+        BuildError::Missing(missing)
+    }
+}
+
+impl From<BrokenRule> for BuildError {
+    fn from(broken: BrokenRule) -> Self {
+        BuildError::Rule(broken)
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    use std::marker::PhantomData;
+
+    use crate::Null;
+
+    /*
+    What a nullable argument accepts: a T, an Option<T> or `null`. `From` can't be used, since
+    core already converts every T into Some(T), so the marker keeps the impls apart.
+    */
+    pub trait IntoNullable<T, Marker> {
+        fn into_nullable(self) -> Option<T>;
+    }
+
+    pub struct FromValue;
+    pub struct FromOption;
+    pub struct FromNull;
+
+    impl<T> IntoNullable<T, FromValue> for T {
+        fn into_nullable(self) -> Option<T> {
+            Some(self)
+        }
+    }
+
+    impl<T> IntoNullable<T, FromOption> for Option<T> {
+        fn into_nullable(self) -> Option<T> {
+            self
+        }
+    }
+
+    impl<T> IntoNullable<T, FromNull> for Null {
+        fn into_nullable(self) -> Option<T> {
+            None
+        }
+    }
+
+    /*
+    Which of the marked arguments of a validator or builder were passed, as a list of const
+    bools: `Marker<true, Marker<false, End>>` after passing the first of two. A setter only names
+    the position it flips, so every setter costs the same no matter how many arguments there are.
+    */
+    pub struct Marker<const PASSED: bool, Rest>(PhantomData<Rest>);
+    pub struct End;
+
+    // Positions in the list, counting from `Here`
+    pub struct Here;
+    pub struct Next<Index>(PhantomData<Index>);
+
+    pub trait Mark<Index> {
+        type Marked;
+    }
+
+    impl<Rest> Mark<Here> for Marker<false, Rest> {
+        type Marked = Marker<true, Rest>;
+    }
+
+    impl<const PASSED: bool, Rest, Index> Mark<Next<Index>> for Marker<PASSED, Rest>
+    where
+        Rest: Mark<Index>,
+    {
+        type Marked = Marker<PASSED, Rest::Marked>;
+    }
+
+    // `I3` is `Next<Next<Next<Here>>>`, so the generated code doesn't have to spell it out
+    macro_rules! indices {
+        ($prev:ident $($next:ident)*) => {
+            pub type $prev = Here;
+            indices!(@next $prev $($next)*);
+        };
+        (@next $prev:ident $next:ident $($rest:ident)*) => {
+            pub type $next = Next<$prev>;
+            indices!(@next $next $($rest)*);
+        };
+        (@next $prev:ident) => {};
+    }
+
+    indices!(
+        I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 I10 I11 I12 I13 I14 I15 I16 I17 I18 I19 I20 I21 I22 I23
+        I24 I25 I26 I27 I28 I29 I30 I31 I32 I33 I34 I35 I36 I37 I38 I39 I40 I41 I42 I43 I44 I45
+        I46 I47 I48 I49 I50 I51 I52 I53 I54 I55 I56 I57 I58 I59 I60 I61 I62 I63
+    );
+
+    #[cfg(feature = "serde")]
+    pub use serde;
+
+    // Wraps the generated Deserialize impls, so leaving the feature off gives one clear error
+    #[cfg(feature = "serde")]
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! __optargs_deserialize {
+        ($($impl:tt)*) => { $($impl)* };
+    }
+
+    #[cfg(not(feature = "serde"))]
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! __optargs_deserialize {
+        ($($impl:tt)*) => {
+            compile_error!("#[optstruct(deserialize)] needs the \"serde\" feature of optargs");
+        };
+    }
+
+    // serde reads `null` as a missing Option<Option<T>>, but nullable fields want Some(None)
+    #[cfg(feature = "serde")]
+    pub fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        T: serde::Deserialize<'de>,
+    {
+        <Option<T> as serde::Deserialize>::deserialize(deserializer).map(Some)
+    }
+}
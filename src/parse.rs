@@ -0,0 +1,347 @@
+//! Runtime support for `#[optfn(parse)]`.
+//!
+//! The attribute generates `plot_from_str`, which calls `plot` with arguments written like a python
+//! call: `x=[1, 2, 3], title='hi'`. It leans on [`parse`] to split the string into raw values, and
+//! converts each of them by its parameter's type:
+//!
+//! - `Vec<T>` takes a list like `[1, 2, 3]`, and converts every item as a `T`
+//! - `&str` borrows from the string, and strips its quotes if it has any
+//! - anything else is parsed with `FromStr`, after stripping quotes
+//!
+//! Strings can be quoted with `'` or `"`, but escapes aren't supported. Passing `None` to an
+//! optional parameter is the same as leaving it out, and `True` and `False` work as booleans.
+//!
+//! ```rust
+//! #[optargs::optfn(parse)]
+//! fn plot(x: Vec<i32>, title: Option<&str>) -> String {
+//!     format!("{}: {:?}", title.unwrap_or("untitled"), x)
+//! }
+//!
+//! assert_eq!(plot_from_str("x=[1, 2, 3], title='hi'").unwrap(), "hi: [1, 2, 3]");
+//! assert_eq!(plot_from_str("x=[]").unwrap(), "untitled: []");
+//! assert!(plot_from_str("title='hi'").is_err());
+//! ```
+//!
+//! `None` is an explicit null for nullable keys, and repeated keys collect every value:
+//!
+//! ```rust
+//! use optargs::parse::ParseError;
+//!
+//! #[optargs::optfn(parse)]
+//! fn update(
+//!     id: u32,
+//!     active: Option<bool>,
+//!     #[optarg(nullable)] email: Option<Option<String>>,
+//!     #[optarg(repeated)] tags: Vec<String>,
+//! ) -> String {
+//!     format!("{} {:?} {:?} {:?}", id, active, email, tags)
+//! }
+//!
+//! assert_eq!(update_from_str("id=1").unwrap(), "1 None None []");
+//! assert_eq!(update_from_str("id=1, email=None").unwrap(), "1 None Some(None) []");
+//! assert_eq!(
+//!     update_from_str("id=1, email='None', active=True").unwrap(),
+//!     r#"1 Some(true) Some(Some("None")) []"#
+//! );
+//! assert_eq!(
+//!     update_from_str("id=1, tags=a, tags='b, c', active=False,").unwrap(),
+//!     r#"1 Some(false) None ["a", "b, c"]"#
+//! );
+//!
+//! // a required key can't be left out with `None`
+//! assert_eq!(update_from_str("id=None"), Err(ParseError::Missing("id")));
+//! assert_eq!(update_from_str("id=1, email=None, email=x"), Err(ParseError::Duplicate("email")));
+//! assert!(matches!(
+//!     update_from_str("id=1, active=yes"),
+//!     Err(ParseError::Invalid { key: "active", .. })
+//! ));
+//! ```
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A single key accepted by a generated `_from_str`.
+#[derive(Debug, Clone, Copy)]
+pub struct Key {
+    pub name: &'static str,
+
+    /// Repeated keys may be passed more than once
+    pub repeated: bool,
+}
+
+/// Everything that can go wrong while parsing a call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The string isn't a list of `key=value` pairs. `at` is the byte offset of the problem.
+    Syntax { at: usize, reason: &'static str },
+
+    /// A key that isn't a parameter of the function
+    Unknown(String),
+
+    /// A required key wasn't passed
+    Missing(&'static str),
+
+    /// A key was passed more than once
+    Duplicate(&'static str),
+
+    /// The value couldn't be converted to the parameter's type
+    Invalid {
+        key: &'static str,
+        value: String,
+        reason: String,
+    },
+
+    /// The keys that were passed break a `group`, `requires` or `conflicts_with` rule
+    Rule(&'static str),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Syntax { at, reason } => write!(f, "{} at offset {}", reason, at),
+            ParseError::Unknown(key) => write!(f, "unexpected argument '{}'", key),
+            ParseError::Missing(key) => write!(f, "missing required argument '{}'", key),
+            ParseError::Duplicate(key) => write!(f, "'{}' was passed more than once", key),
+            ParseError::Invalid { key, value, reason } => {
+                write!(f, "invalid value '{}' for '{}': {}", value, key, reason)
+            }
+            ParseError::Rule(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Split a call into the raw values of every key, in the same order as `keys`.
+///
+/// Commas, brackets and `=` inside quotes are part of the value, and a single trailing comma is
+/// allowed, in lists too:
+///
+/// ```rust
+/// use optargs::parse::{parse, Key, ParseError};
+///
+/// let keys = [
+///     Key { name: "x", repeated: false },
+///     Key { name: "title", repeated: false },
+///     Key { name: "tag", repeated: true },
+/// ];
+///
+/// let values = parse(&keys, "x=[1, 2,], title='a, b] = c', tag=\"[\", tag=d,").unwrap();
+/// assert_eq!(values, vec![vec!["[1, 2,]"], vec!["'a, b] = c'"], vec!["\"[\"", "d"]]);
+/// assert_eq!(parse(&keys, "").unwrap(), vec![Vec::<&str>::new(); 3]);
+///
+/// let syntax = |at, reason| Err(ParseError::Syntax { at, reason });
+/// assert_eq!(parse(&keys, "x=1,,"), syntax(4, "expected `key=value`"));
+/// assert_eq!(parse(&keys, ","), syntax(0, "expected `key=value`"));
+/// assert_eq!(parse(&keys, "x=[1, 2"), syntax(2, "unclosed bracket"));
+/// assert_eq!(parse(&keys, "x=[(1], 2)"), syntax(5, "unexpected closing bracket"));
+/// assert_eq!(parse(&keys, "x=1]"), syntax(3, "unexpected closing bracket"));
+/// assert_eq!(parse(&keys, "x=1, title='hi"), syntax(11, "unclosed string"));
+/// assert_eq!(parse(&keys, "x=1, title="), syntax(11, "expected a value"));
+/// assert_eq!(parse(&keys, "x=1, =2"), syntax(5, "expected a key"));
+///
+/// assert_eq!(parse(&keys, "x=1, x=2"), Err(ParseError::Duplicate("x")));
+/// assert_eq!(parse(&keys, "y=1"), Err(ParseError::Unknown("y".to_string())));
+/// ```
+pub fn parse<'s>(keys: &[Key], input: &'s str) -> Result<Vec<Vec<&'s str>>, ParseError> {
+    let mut values = vec![Vec::new(); keys.len()];
+
+    let pairs = split(input).map_err(|(at, reason)| ParseError::Syntax { at, reason })?;
+    let count = pairs.len();
+    for (idx, (at, pair)) in pairs.into_iter().enumerate() {
+        // an empty string and `x=1,` are fine, but `x=1,,` isn't
+        if pair.trim().is_empty() && idx + 1 == count {
+            break;
+        }
+
+        let (key, value) = match pair.find('=') {
+            Some(eq) => (pair[..eq].trim(), pair[eq + 1..].trim()),
+            None => {
+                let at = at + (pair.len() - pair.trim_start().len());
+                return Err(ParseError::Syntax {
+                    at,
+                    reason: "expected `key=value`",
+                });
+            }
+        };
+
+        let valid = key.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !valid {
+            let at = at + (pair.len() - pair.trim_start().len());
+            return Err(ParseError::Syntax {
+                at,
+                reason: "expected a key",
+            });
+        }
+        if value.is_empty() {
+            return Err(ParseError::Syntax {
+                at: at + pair.len(),
+                reason: "expected a value",
+            });
+        }
+
+        let id = match keys.iter().position(|k| k.name == key) {
+            Some(id) => id,
+            None => return Err(ParseError::Unknown(key.to_string())),
+        };
+        if !keys[id].repeated && !values[id].is_empty() {
+            return Err(ParseError::Duplicate(keys[id].name));
+        }
+        values[id].push(value);
+    }
+
+    Ok(values)
+}
+
+// Split on the commas that aren't inside brackets or quotes, keeping the offset of every part
+fn split(input: &str) -> Result<Vec<(usize, &str)>, (usize, &'static str)> {
+    let mut parts = Vec::new();
+    let mut brackets = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+
+    for (at, c) in input.char_indices() {
+        match (quote, c) {
+            (Some((q, _)), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some((c, at)),
+            (None, '[') | (None, '(') | (None, '{') => brackets.push((c, at)),
+            (None, ']') | (None, ')') | (None, '}') => {
+                let open = match c {
+                    ']' => '[',
+                    ')' => '(',
+                    _ => '{',
+                };
+                match brackets.pop() {
+                    Some((o, _)) if o == open => {}
+                    _ => return Err((at, "unexpected closing bracket")),
+                }
+            }
+            (None, ',') if brackets.is_empty() => {
+                parts.push((start, &input[start..at]));
+                start = at + 1;
+            }
+            (None, _) => {}
+        }
+    }
+
+    if let Some((_, at)) = quote {
+        return Err((at, "unclosed string"));
+    }
+    if let Some((_, at)) = brackets.pop() {
+        return Err((at, "unclosed bracket"));
+    }
+    parts.push((start, &input[start..]));
+    Ok(parts)
+}
+
+/// Convert the value of a required key.
+pub fn required<'s, T>(
+    key: &Key,
+    values: Vec<&'s str>,
+    convert: impl Fn(&'s str) -> Result<T, String>,
+) -> Result<T, ParseError> {
+    optional(key, values, convert)?.ok_or(ParseError::Missing(key.name))
+}
+
+/// Convert the value of an optional key, where `None` is the same as leaving it out.
+pub fn optional<'s, T>(
+    key: &Key,
+    values: Vec<&'s str>,
+    convert: impl Fn(&'s str) -> Result<T, String>,
+) -> Result<Option<T>, ParseError> {
+    match values.first().copied() {
+        None | Some("None") => Ok(None),
+        Some(value) => convert(value).map(Some).map_err(|e| invalid(key, value, e)),
+    }
+}
+
+/// Convert the value of a nullable key, where `None` is an explicit null.
+pub fn nullable<'s, T>(
+    key: &Key,
+    values: Vec<&'s str>,
+    convert: impl Fn(&'s str) -> Result<T, String>,
+) -> Result<Option<Option<T>>, ParseError> {
+    match values.first().copied() {
+        Some("None") => Ok(Some(None)),
+        _ => optional(key, values, convert).map(|value| value.map(Some)),
+    }
+}
+
+/// Convert every value of a repeated key, which is left out if it was never passed.
+pub fn repeated<'s, T>(
+    key: &Key,
+    values: Vec<&'s str>,
+    convert: impl Fn(&'s str) -> Result<T, String>,
+) -> Result<Option<Vec<T>>, ParseError> {
+    if values.is_empty() {
+        return Ok(None);
+    }
+    values
+        .into_iter()
+        .map(|value| convert(value).map_err(|e| invalid(key, value, e)))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+fn invalid(key: &Key, value: &str, reason: String) -> ParseError {
+    ParseError::Invalid {
+        key: key.name,
+        value: value.to_string(),
+        reason,
+    }
+}
+
+/// Convert a value with `FromStr`, after stripping its quotes.
+pub fn value<T>(raw: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let raw = match raw {
+        "True" => "true",
+        "False" => "false",
+        raw => unquote(raw),
+    };
+    T::from_str(raw).map_err(|e| e.to_string())
+}
+
+/// Borrow a string value, without its quotes.
+pub fn string(raw: &str) -> Result<&str, String> {
+    Ok(unquote(raw))
+}
+
+/// Convert a list like `[1, 2, 3]`, one item at a time.
+pub fn list<'s, T>(
+    raw: &'s str,
+    item: impl Fn(&'s str) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    let inner = raw
+        .strip_prefix('[')
+        .and_then(|raw| raw.strip_suffix(']'))
+        .ok_or_else(|| "expected a list like [1, 2, 3]".to_string())?;
+
+    let items = split(inner).map_err(|(_, reason)| reason.to_string())?;
+    let count = items.len();
+    let mut out = Vec::with_capacity(count);
+    for (idx, (_, raw)) in items.into_iter().enumerate() {
+        let raw = raw.trim();
+        // `[]` and `[1, 2,]` are fine
+        if raw.is_empty() && idx + 1 == count {
+            break;
+        }
+        out.push(item(raw)?);
+    }
+    Ok(out)
+}
+
+fn unquote(raw: &str) -> &str {
+    for &q in &['\'', '"'] {
+        if raw.len() >= 2 && raw.starts_with(q) && raw.ends_with(q) {
+            return &raw[1..raw.len() - 1];
+        }
+    }
+    raw
+}
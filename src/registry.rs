@@ -0,0 +1,161 @@
+//! Runtime support for `#[optfn(register)]`.
+//!
+//! Every registered function gets a `REGISTRATION` next to its validator, which wraps the function
+//! in one that takes its arguments as `(key, Box<dyn Any>)` pairs. Rust has no way to run code
+//! before `main`, so registrations are added to the [`Registry`] by hand, once at startup:
+//!
+//! ```rust
+//! use std::any::Any;
+//!
+//! #[optargs::optfn(register)]
+//! fn plot(x: Vec<i32>, title: Option<&str>) -> usize {
+//!     x.len() + title.map_or(0, str::len)
+//! }
+//!
+//! fn main() {
+//!     optargs::Registry::register(plot::REGISTRATION);
+//!
+//!     let args: optargs::registry::Args = vec![("x", Box::new(vec![1, 2, 3])), ("title", Box::new("hi"))];
+//!     let out = optargs::Registry::call("plot", args).unwrap();
+//!     assert_eq!(out.downcast_ref::<usize>(), Some(&5));
+//! }
+//! ```
+//!
+//! The wrapper names the function by its path, so it has to be defined in a module and not inside
+//! another function.
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+
+/// The arguments of a registered function, as `(key, value)` pairs.
+pub type Args<'a> = Vec<(&'a str, Box<dyn Any>)>;
+
+/// A type-erased function, as generated by `#[optfn(register)]`.
+#[derive(Clone, Copy)]
+pub struct Registration {
+    /// The name the function is called by
+    pub name: &'static str,
+
+    /// Checks and downcasts the arguments, then calls the function and boxes its output
+    pub call: fn(Args) -> Result<Box<dyn Any>, ArgError>,
+}
+
+// `call` is generic over the lifetime of its keys, which older compilers can't derive `Debug` for
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Everything that can go wrong while calling a registered function.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgError {
+    /// No function was registered under this name
+    UnknownFunction(String),
+
+    /// A key that isn't an argument of the function
+    Unknown { function: &'static str, key: String },
+
+    /// A required argument wasn't passed
+    Missing {
+        function: &'static str,
+        key: &'static str,
+    },
+
+    /// The value doesn't have the argument's type
+    WrongType {
+        function: &'static str,
+        key: &'static str,
+        expected: &'static str,
+    },
+
+    /// The arguments break a `group`, `requires` or `conflicts_with` rule
+    Rule {
+        function: &'static str,
+        message: &'static str,
+    },
+}
+
+impl Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::UnknownFunction(name) => write!(f, "no function named `{}`", name),
+            ArgError::Unknown { function, key } => {
+                write!(f, "`{}` has no argument `{}`", function, key)
+            }
+            ArgError::Missing { function, key } => {
+                write!(f, "missing required argument `{}` of `{}`", key, function)
+            }
+            ArgError::WrongType {
+                function,
+                key,
+                expected,
+            } => write!(
+                f,
+                "argument `{}` of `{}` must be a `{}`",
+                key, function, expected
+            ),
+            ArgError::Rule { function, message } => write!(f, "{} in `{}`", message, function),
+        }
+    }
+}
+
+impl Error for ArgError {}
+
+static REGISTRY: Mutex<Vec<Registration>> = Mutex::new(Vec::new());
+
+/// The functions that can be called by name.
+pub struct Registry;
+
+impl Registry {
+    /// Make a function callable by name, replacing any function registered under the same name.
+    pub fn register(registration: Registration) {
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.retain(|r| r.name != registration.name);
+        registry.push(registration);
+    }
+
+    /// The names of every registered function, in the order they were registered.
+    pub fn names() -> Vec<&'static str> {
+        let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.iter().map(|r| r.name).collect()
+    }
+
+    /// Call a registered function with `(key, value)` pairs, and box whatever it returns.
+    pub fn call<'a, I>(name: &str, args: I) -> Result<Box<dyn Any>, ArgError>
+    where
+        I: IntoIterator<Item = (&'a str, Box<dyn Any>)>,
+    {
+        // the lock is released before calling, so registered functions can call each other
+        let call = {
+            let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+            registry
+                .iter()
+                .find(|r| r.name == name)
+                .map(|r| r.call)
+                .ok_or_else(|| ArgError::UnknownFunction(name.to_string()))?
+        };
+        call(args.into_iter().collect())
+    }
+}
+
+/// Downcast the value of an argument, used by the generated wrappers.
+#[doc(hidden)]
+pub fn downcast<T: Any>(
+    function: &'static str,
+    key: &'static str,
+    value: Box<dyn Any>,
+) -> Result<T, ArgError> {
+    value
+        .downcast::<T>()
+        .map(|value| *value)
+        .map_err(|_| ArgError::WrongType {
+            function,
+            key,
+            expected: std::any::type_name::<T>(),
+        })
+}